@@ -0,0 +1,152 @@
+#ifndef RZEN_H
+#define RZEN_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. Regenerate with: cbindgen --config cbindgen.toml -o include/rzen.h */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+enum RzenKind
+#ifdef __cplusplus
+  : uint32_t
+#endif // __cplusplus
+ {
+  RZEN_KIND_FRACTAL = 0,
+  RZEN_KIND_PEN = 1,
+  RZEN_KIND_SEGMENT = 2,
+  RZEN_KIND_PIVOT = 3,
+  RZEN_KIND_SIGNAL = 4,
+  RZEN_KIND_POINT = 5,
+  RZEN_KIND_CANDLE = 6,
+};
+#ifndef __cplusplus
+typedef uint32_t RzenKind;
+#endif // __cplusplus
+
+enum RzenAction
+#ifdef __cplusplus
+  : uint32_t
+#endif // __cplusplus
+ {
+  RZEN_ACTION_NEW = 0,
+  RZEN_ACTION_UPDATE = 1,
+  RZEN_ACTION_CONFIRM = 2,
+  RZEN_ACTION_RETRACT = 3,
+};
+#ifndef __cplusplus
+typedef uint32_t RzenAction;
+#endif // __cplusplus
+
+typedef struct RzenIndicator RzenIndicator;
+
+typedef struct RzenAnalyzer RzenAnalyzer;
+
+typedef struct RzenEvent {
+  RzenKind kind;
+  RzenAction action;
+  uint64_t id;
+  uint32_t level;
+  uint32_t signal;
+  int32_t side;
+  int64_t from_time;
+  double from_price;
+  int64_t to_time;
+  double to_price;
+} RzenEvent;
+
+typedef struct RzenBuffers {
+  double *fractal_top;
+  double *fractal_bottom;
+  double *pen;
+  double *segment;
+  double *pivot_high;
+  double *pivot_low;
+} RzenBuffers;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ 创建Analyzer，用rzen_analyzer_free释放，失败时返回NULL
+ */
+struct RzenAnalyzer *rzen_analyzer_new(void);
+
+/*
+ # Safety
+ analyzer必须是rzen_analyzer_new返回的指针或者NULL，释放之后不能再使用
+ */
+void rzen_analyzer_free(struct RzenAnalyzer *analyzer);
+
+/*
+ 输入一根完成的K线，time为毫秒，返回尚未取出的事件数量
+ analyzer为NULL或者处理失败时不做任何处理，返回0
+
+ # Safety
+ analyzer必须是rzen_analyzer_new返回的有效指针或者NULL
+ */
+size_t rzen_analyzer_push_bar(struct RzenAnalyzer *analyzer,
+                              int64_t time,
+                              double open,
+                              double high,
+                              double low,
+                              double close);
+
+/*
+ 尚未取出的事件数量，analyzer为NULL时返回0
+
+ # Safety
+ analyzer必须是rzen_analyzer_new返回的有效指针或者NULL
+ */
+size_t rzen_analyzer_pending_events(const struct RzenAnalyzer *analyzer);
+
+/*
+ 按发生的顺序取出最多capacity个事件写入events，返回写入的数量
+ analyzer或events为NULL时返回0
+
+ # Safety
+ analyzer必须是rzen_analyzer_new返回的有效指针或者NULL，events至少有capacity个元素
+ */
+size_t rzen_analyzer_poll_events(struct RzenAnalyzer *analyzer,
+                                 struct RzenEvent *events,
+                                 size_t capacity);
+
+/*
+ 创建指标缓冲区的计算器，level为显示的中枢级别：0为笔中枢，1为线段中枢
+ 用rzen_buffers_free释放，level不支持或者失败时返回NULL
+ */
+struct RzenIndicator *rzen_buffers_new(uint32_t level);
+
+/*
+ # Safety
+ indicator必须是rzen_buffers_new返回的指针或者NULL，释放之后不能再使用
+ */
+void rzen_buffers_free(struct RzenIndicator *indicator);
+
+/*
+ 在OnCalculate中调用，参数与OnCalculate相同，time为毫秒，数组按时间从旧到新排列(ArraySetAsSeries为false)
+ 返回值作为OnCalculate的返回值，first_changed为输出发生变化的起始下标，可以为NULL
+ indicator、数组或缓冲区为NULL以及计算失败时不做任何处理，返回0，下一次重新计算
+
+ # Safety
+ indicator必须是rzen_buffers_new返回的有效指针或者NULL，所有数组至少有rates_total个元素
+ */
+size_t rzen_buffers_calculate(struct RzenIndicator *indicator,
+                              size_t rates_total,
+                              size_t prev_calculated,
+                              const int64_t *time,
+                              const double *open,
+                              const double *high,
+                              const double *low,
+                              const double *close,
+                              const struct RzenBuffers *buffers,
+                              size_t *first_changed);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RZEN_H */
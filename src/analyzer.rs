@@ -1,46 +1,621 @@
 use crate::bar::Bar;
+use crate::decompose::{Decomposer, Move};
+use crate::divergence::{
+    ConsolidationDivergence, ConsolidationDivergenceDetector, Divergence, DivergenceDetector,
+};
+use crate::emitter::{Emitter, SubscriberId};
 use crate::fractal::Fractal;
 use crate::fractal_detector::FractalDetector;
-use crate::pen_detector::PenDetector;
-use crate::sd2::SegmentDetector;
+use crate::lifecycle::{LifecycleEvent, LifecycleTracker, Object, ObjectId, ObjectKind, Tracked};
+use crate::line::Line;
+use crate::macd::Macd;
+use crate::pen_detector::{PenDetector, PenEvent, PenRule};
+use crate::pivot::{PenPivotDetector, Pivot, PivotEvent, PivotLevel, SegmentPivotDetector};
+use crate::recursive::RecursiveDetector;
+use crate::sd2::{SegmentDetector, SegmentEvent};
+use crate::signals::{Signal, SignalDetector};
+use crate::time::Time;
+use crate::trend::{Trend, TrendDetector, TrendEvent};
+
+// 未完成的K线上得到的临时结果，下一次更新或者K线完成时失效
+#[derive(Debug, Clone)]
+pub enum ProvisionalEvent {
+    Fractal(Fractal),
+    Pen(PenEvent),
+}
+
+// Analyzer在处理K线过程中产生的事件
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum AnalyzerEvent {
+    // 包含处理完成的K线
+    Candle(Bar),
+    // 新的K线与正在形成的K线合并，合并后的K线发生变化
+    CandleUpdate(Bar),
+    Fractal(Fractal),
+    Pen(PenEvent),
+    Segment(SegmentEvent),
+    // 笔中枢和线段中枢
+    Pivot(PivotEvent),
+    Trend(TrendEvent),
+    Divergence(Divergence),
+    ConsolidationDivergence(ConsolidationDivergence),
+    Signal(Signal),
+    Lifecycle(LifecycleEvent),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Candle,
+    CandleUpdate,
+    Fractal,
+    Pen,
+    Segment,
+    Pivot,
+    Trend,
+    Divergence,
+    ConsolidationDivergence,
+    Signal,
+    Lifecycle,
+}
+
+impl AnalyzerEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            AnalyzerEvent::Candle(_) => EventKind::Candle,
+            AnalyzerEvent::CandleUpdate(_) => EventKind::CandleUpdate,
+            AnalyzerEvent::Fractal(_) => EventKind::Fractal,
+            AnalyzerEvent::Pen(_) => EventKind::Pen,
+            AnalyzerEvent::Segment(_) => EventKind::Segment,
+            AnalyzerEvent::Pivot(_) => EventKind::Pivot,
+            AnalyzerEvent::Trend(_) => EventKind::Trend,
+            AnalyzerEvent::Divergence(_) => EventKind::Divergence,
+            AnalyzerEvent::ConsolidationDivergence(_) => EventKind::ConsolidationDivergence,
+            AnalyzerEvent::Signal(_) => EventKind::Signal,
+            AnalyzerEvent::Lifecycle(_) => EventKind::Lifecycle,
+        }
+    }
+}
+
+// ID按出现的先后顺序分配，对象按ID有序保存
+fn find<T>(objects: &[Tracked<T>], id: ObjectId) -> Option<usize> {
+    objects.binary_search_by_key(&id, |o| o.id).ok()
+}
+
+fn update<T>(objects: &mut [Tracked<T>], id: ObjectId, value: T) {
+    if let Some(i) = find(objects, id) {
+        objects[i].value = value;
+    }
+}
+
+fn confirm<T>(objects: &mut [Tracked<T>], id: ObjectId) {
+    if let Some(i) = find(objects, id) {
+        objects[i].confirmed = true;
+    }
+}
+
+fn retract<T>(objects: &mut Vec<Tracked<T>>, id: ObjectId) {
+    if let Some(i) = find(objects, id) {
+        objects.remove(i);
+    }
+}
+
+fn containing(lines: &[Tracked<Line>], time: Time) -> Option<&Tracked<Line>> {
+    let i = lines.partition_point(|l| l.value.to().time() < time);
+    lines.get(i).filter(|l| l.value.from().time() <= time)
+}
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Analyzer {
     fd: FractalDetector,
     pd: PenDetector,
     sd: SegmentDetector,
-    fractals: Vec<Fractal>,
-    pens: Vec<usize>,
-    segments: Vec<usize>,
+    ppd: PenPivotDetector,
+    spd: SegmentPivotDetector,
+    pen_trend: TrendDetector,
+    segment_trend: TrendDetector,
+    decomposer: Decomposer,
+    // 以线段为笔递归得到的高级别线段
+    recursive: RecursiveDetector,
+    macd: Macd,
+    pen_divergence: DivergenceDetector,
+    segment_divergence: DivergenceDetector,
+    pen_consolidation_divergence: ConsolidationDivergenceDetector,
+    segment_consolidation_divergence: ConsolidationDivergenceDetector,
+    pen_signal: SignalDetector,
+    segment_signal: SignalDetector,
+    lifecycle: LifecycleTracker,
+    // 包含处理之后的K线、分型、笔的端点、笔和线段的完整历史，被撤销的对象不保留
+    candles: Vec<Tracked<Bar>>,
+    fractals: Vec<Tracked<Fractal>>,
+    points: Vec<Tracked<Fractal>>,
+    pens: Vec<Tracked<Line>>,
+    segments: Vec<Tracked<Line>>,
+    // 已经结束的笔中枢和线段中枢，按结束的先后顺序保存
+    pivots: Vec<Pivot>,
+    // 已经结束的走势
+    trends: Vec<Trend>,
+    // 笔中枢和线段中枢的趋势背驰
+    divergences: Vec<Divergence>,
+    // 笔中枢和线段中枢的盘整背驰
+    consolidation_divergences: Vec<ConsolidationDivergence>,
+    // 买卖点，按发出的先后顺序保存
+    signals: Vec<Signal>,
+    // 当前未完成K线上的临时结果
+    #[cfg_attr(feature = "serde", serde(skip))]
+    provisional: Vec<ProvisionalEvent>,
+    // 最近一根K线产生的生命周期事件
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lifecycle_events: Vec<LifecycleEvent>,
+    // 订阅者不属于分析状态，恢复之后需要重新订阅
+    #[cfg_attr(feature = "serde", serde(skip, default = "Emitter::new"))]
+    emitter: Emitter<'static, AnalyzerEvent>,
 }
 
 impl Analyzer {
     pub fn new() -> Self {
+        Self::with_rule(PenRule::Standard)
+    }
+
+    // 按指定的笔划分规则分析
+    pub fn with_rule(rule: PenRule) -> Self {
         Self {
-            fd: FractalDetector::new(),
-            pd: PenDetector::new(),
+            fd: FractalDetector::with_candles(),
+            pd: PenDetector::with_rule(rule),
             sd: SegmentDetector::new(),
+            ppd: PenPivotDetector::new(),
+            spd: SegmentPivotDetector::new(),
+            pen_trend: TrendDetector::new(PivotLevel::Pen),
+            segment_trend: TrendDetector::new(PivotLevel::Segment),
+            decomposer: Decomposer::new(),
+            recursive: RecursiveDetector::new(),
+            macd: Macd::standard(),
+            pen_divergence: DivergenceDetector::new(PivotLevel::Pen),
+            segment_divergence: DivergenceDetector::new(PivotLevel::Segment),
+            pen_consolidation_divergence: ConsolidationDivergenceDetector::new(PivotLevel::Pen),
+            segment_consolidation_divergence: ConsolidationDivergenceDetector::new(
+                PivotLevel::Segment,
+            ),
+            pen_signal: SignalDetector::new(PivotLevel::Pen),
+            segment_signal: SignalDetector::new(PivotLevel::Segment),
+            lifecycle: LifecycleTracker::new(),
+            candles: Vec::new(),
             fractals: Vec::new(),
+            points: Vec::new(),
             pens: Vec::new(),
             segments: Vec::new(),
+            pivots: Vec::new(),
+            trends: Vec::new(),
+            divergences: Vec::new(),
+            consolidation_divergences: Vec::new(),
+            signals: Vec::new(),
+            provisional: Vec::new(),
+            lifecycle_events: Vec::new(),
+            emitter: Emitter::new(),
+        }
+    }
+
+    // 已经完成的包含处理之后的K线
+    pub fn candles(&self) -> &[Tracked<Bar>] {
+        &self.candles
+    }
+
+    // 分型检测得到的所有分型
+    pub fn fractals(&self) -> &[Tracked<Fractal>] {
+        &self.fractals
+    }
+
+    // 笔的端点
+    pub fn pen_points(&self) -> &[Tracked<Fractal>] {
+        &self.points
+    }
+
+    pub fn pens(&self) -> &[Tracked<Line>] {
+        &self.pens
+    }
+
+    // 已经确认的线段和正在延伸的线段
+    pub fn segments(&self) -> &[Tracked<Line>] {
+        &self.segments
+    }
+
+    pub fn pen(&self, id: ObjectId) -> Option<&Tracked<Line>> {
+        find(&self.pens, id).map(|i| &self.pens[i])
+    }
+
+    pub fn segment(&self, id: ObjectId) -> Option<&Tracked<Line>> {
+        find(&self.segments, id).map(|i| &self.segments[i])
+    }
+
+    // time所在的笔，端点属于以它为终点的笔
+    pub fn pen_at(&self, time: Time) -> Option<&Tracked<Line>> {
+        containing(&self.pens, time)
+    }
+
+    // time所在的线段，线段重新开始寻找时两个线段之间可能有空隙
+    pub fn segment_containing(&self, time: Time) -> Option<&Tracked<Line>> {
+        containing(&self.segments, time)
+    }
+
+    pub fn last_confirmed_pen(&self) -> Option<&Tracked<Line>> {
+        self.pens.iter().rev().find(|p| p.confirmed)
+    }
+
+    pub fn last_confirmed_segment(&self) -> Option<&Tracked<Line>> {
+        self.segments.iter().rev().find(|s| s.confirmed)
+    }
+
+    pub fn pivots(&self) -> &[Pivot] {
+        &self.pivots
+    }
+
+    // 正在延伸中的笔中枢
+    pub fn pen_pivot(&self) -> Option<&Pivot> {
+        self.ppd.pivot()
+    }
+
+    // 正在延伸中的线段中枢
+    pub fn segment_pivot(&self) -> Option<&Pivot> {
+        self.spd.pivot()
+    }
+
+    pub fn trends(&self) -> &[Trend] {
+        &self.trends
+    }
+
+    // 由笔中枢构成的当前走势
+    pub fn pen_trend(&self) -> Option<&Trend> {
+        self.pen_trend.current()
+    }
+
+    // 由线段中枢构成的当前走势
+    pub fn segment_trend(&self) -> Option<&Trend> {
+        self.segment_trend.current()
+    }
+
+    // 同级别分解得到的走势
+    pub fn moves(&self) -> &[Move] {
+        self.decomposer.moves()
+    }
+
+    pub fn recursive(&self) -> &RecursiveDetector {
+        &self.recursive
+    }
+
+    pub fn macd(&self) -> &Macd {
+        &self.macd
+    }
+
+    pub fn divergences(&self) -> &[Divergence] {
+        &self.divergences
+    }
+
+    pub fn consolidation_divergences(&self) -> &[ConsolidationDivergence] {
+        &self.consolidation_divergences
+    }
+
+    pub fn signals(&self) -> &[Signal] {
+        &self.signals
+    }
+
+    pub fn provisional(&self) -> &[ProvisionalEvent] {
+        &self.provisional
+    }
+
+    pub fn lifecycle_events(&self) -> &[LifecycleEvent] {
+        &self.lifecycle_events
+    }
+
+    // 订阅所有事件，在on_new_bar中按事件发生的顺序调用
+    pub fn subscribe<F>(&mut self, observer: F) -> SubscriberId
+    where
+        F: 'static + FnMut(&AnalyzerEvent),
+    {
+        self.emitter.subscribe(observer)
+    }
+
+    // 只订阅指定类型的事件
+    pub fn subscribe_kinds<F>(&mut self, kinds: &[EventKind], observer: F) -> SubscriberId
+    where
+        F: 'static + FnMut(&AnalyzerEvent),
+    {
+        let kinds = kinds.to_vec();
+        self.emitter
+            .subscribe_filter(move |e: &AnalyzerEvent| kinds.contains(&e.kind()), observer)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriberId) -> bool {
+        self.emitter.unsubscribe(id)
+    }
+
+    // 没有订阅者时不构造事件
+    fn emit<F>(&mut self, event: F)
+    where
+        F: FnOnce() -> AnalyzerEvent,
+    {
+        if !self.emitter.is_empty() {
+            self.emitter.emit(&event());
+        }
+    }
+
+    fn on_signals<I>(&mut self, signals: I)
+    where
+        I: IntoIterator<Item = Signal>,
+    {
+        for signal in signals {
+            self.emit(|| AnalyzerEvent::Signal(signal.clone()));
+            self.signals.push(signal);
+        }
+    }
+
+    fn on_divergence(&mut self, divergence: Divergence) {
+        self.emit(|| AnalyzerEvent::Divergence(divergence.clone()));
+        self.divergences.push(divergence);
+    }
+
+    fn on_consolidation_divergence(&mut self, divergence: Option<ConsolidationDivergence>) {
+        if let Some(divergence) = divergence {
+            self.emit(|| AnalyzerEvent::ConsolidationDivergence(divergence.clone()));
+            self.consolidation_divergences.push(divergence);
+        }
+    }
+
+    fn on_lifecycle_events(&mut self, events: Vec<LifecycleEvent>) {
+        for event in &events {
+            self.emit(|| AnalyzerEvent::Lifecycle(event.clone()));
+            match event {
+                LifecycleEvent::New(id, object) => match object {
+                    Object::Candle(bar) => self.candles.push(Tracked::new(*id, bar.clone())),
+                    Object::Fractal(f) => self.fractals.push(Tracked::new(*id, f.clone())),
+                    Object::Point(f) => self.points.push(Tracked::new(*id, f.clone())),
+                    Object::Pen(line) => self.pens.push(Tracked::new(*id, *line)),
+                    Object::Segment(line) => self.segments.push(Tracked::new(*id, *line)),
+                    Object::Pivot(_) => {}
+                },
+                LifecycleEvent::Update(id, object) => match object {
+                    Object::Pen(line) => update(&mut self.pens, *id, *line),
+                    Object::Segment(line) => update(&mut self.segments, *id, *line),
+                    _ => {}
+                },
+                LifecycleEvent::Confirm(kind, id) => match kind {
+                    ObjectKind::Candle => confirm(&mut self.candles, *id),
+                    ObjectKind::Fractal => confirm(&mut self.fractals, *id),
+                    ObjectKind::Point => confirm(&mut self.points, *id),
+                    ObjectKind::Pen => confirm(&mut self.pens, *id),
+                    ObjectKind::Segment => confirm(&mut self.segments, *id),
+                    ObjectKind::Pivot => {}
+                },
+                LifecycleEvent::Retract(kind, id) => match kind {
+                    ObjectKind::Candle => retract(&mut self.candles, *id),
+                    ObjectKind::Fractal => retract(&mut self.fractals, *id),
+                    ObjectKind::Point => retract(&mut self.points, *id),
+                    ObjectKind::Pen => retract(&mut self.pens, *id),
+                    ObjectKind::Segment => retract(&mut self.segments, *id),
+                    ObjectKind::Pivot => {}
+                },
+            }
+        }
+        self.lifecycle_events.extend(events);
+    }
+
+    fn on_pen_pivot_event(&mut self, event: PivotEvent, time: Time) {
+        for trend_event in self.pen_trend.on_pivot_event(&event) {
+            self.on_trend_event(trend_event);
+        }
+        let divergence =
+            self.pen_divergence
+                .on_pivot_event(&event, self.pen_trend.current(), &self.macd);
+        self.pen_signal.on_pivot_event(&event);
+        if let Some(divergence) = divergence {
+            let signal = self.pen_signal.on_divergence(&divergence, time);
+            self.on_signals(signal);
+            self.on_divergence(divergence);
+        }
+        let divergence = self.pen_consolidation_divergence.on_pivot_event(
+            &event,
+            self.pen_trend.current(),
+            &self.macd,
+        );
+        self.on_consolidation_divergence(divergence);
+        self.on_pivot_event(event);
+    }
+
+    fn on_segment_pivot_event(&mut self, event: PivotEvent, time: Time) {
+        for trend_event in self.segment_trend.on_pivot_event(&event) {
+            self.on_trend_event(trend_event);
+        }
+        let divergence = self.segment_divergence.on_pivot_event(
+            &event,
+            self.segment_trend.current(),
+            &self.macd,
+        );
+        self.segment_signal.on_pivot_event(&event);
+        if let Some(divergence) = divergence {
+            let signal = self.segment_signal.on_divergence(&divergence, time);
+            self.on_signals(signal);
+            self.on_divergence(divergence);
+        }
+        let divergence = self.segment_consolidation_divergence.on_pivot_event(
+            &event,
+            self.segment_trend.current(),
+            &self.macd,
+        );
+        self.on_consolidation_divergence(divergence);
+        self.on_pivot_event(event);
+    }
+
+    fn on_pivot_event(&mut self, event: PivotEvent) {
+        self.emit(|| AnalyzerEvent::Pivot(event.clone()));
+        let events = self.lifecycle.on_pivot_event(&event);
+        self.on_lifecycle_events(events);
+        if let PivotEvent::Finish(pivot, _) = event {
+            self.pivots.push(pivot);
+        }
+    }
+
+    // 之后计算MACD面积可能用到的最早时间，中枢检测还没有开始时返回None
+    fn macd_horizon(&self) -> Option<Time> {
+        let pivots = self.ppd.earliest()?.min(self.spd.earliest()?);
+        let divergences = [
+            &self.pen_consolidation_divergence,
+            &self.segment_consolidation_divergence,
+        ];
+        Some(
+            divergences
+                .iter()
+                .filter_map(|d| d.earliest())
+                .fold(pivots, Time::min),
+        )
+    }
+
+    fn on_trend_event(&mut self, event: TrendEvent) {
+        self.emit(|| AnalyzerEvent::Trend(event.clone()));
+        if let TrendEvent::Finish(trend) = event {
+            self.trends.push(trend);
         }
     }
 
     pub fn on_new_bar(&mut self, bar: &Bar) {
+        self.lifecycle_events.clear();
+        self.macd.on_new_bar(bar);
+        // 价格回到中枢，取消之前的第三类买卖点
+        let signal = self.pen_signal.on_new_bar(bar);
+        self.on_signals(signal);
+        let signal = self.segment_signal.on_new_bar(bar);
+        self.on_signals(signal);
+        let last = self.fd.last_candle().map(|c| (c.index, c.bar.clone()));
         let fractal = self.fd.on_new_bar(bar);
+        for candle in self.fd.take_candles() {
+            self.emit(|| AnalyzerEvent::Candle(candle.clone()));
+            let events = self.lifecycle.on_candle(&candle);
+            self.on_lifecycle_events(events);
+        }
+        if let (Some((index, before)), Some(current)) = (last, self.fd.last_candle()) {
+            let after = &current.bar;
+            let is_changed = before.time != after.time
+                || before.open != after.open
+                || before.high != after.high
+                || before.low != after.low
+                || before.close != after.close
+                || before.volume != after.volume;
+            if current.index == index && is_changed {
+                let after = after.clone();
+                self.emit(move || AnalyzerEvent::CandleUpdate(after));
+            }
+        }
         if let Some(f) = fractal {
+            self.emit(|| AnalyzerEvent::Fractal(f.clone()));
+            let events = self.lifecycle.on_fractal(&f);
+            self.on_lifecycle_events(events);
             let pe = self.pd.on_new_fractal(f);
             if let Some(pen_event) = pe {
-                self.sd.on_pen_event(pen_event);
+                self.emit(|| AnalyzerEvent::Pen(pen_event.clone()));
+                let events = self.lifecycle.on_pen_event(&pen_event);
+                self.on_lifecycle_events(events);
+                for pivot_event in self.ppd.on_pen_event(&pen_event) {
+                    self.on_pen_pivot_event(pivot_event, bar.time);
+                }
+                // 中枢事件处理之后才能确认第二、三类买卖点
+                let signals = self.pen_signal.on_pen_event(&pen_event, bar.time);
+                self.on_signals(signals);
+                self.segment_signal.on_pen_event(&pen_event, bar.time);
+                let segment_event = self.sd.on_pen_event(pen_event);
+                if let Some(segment_event) = segment_event.as_ref() {
+                    self.emit(|| AnalyzerEvent::Segment(segment_event.clone()));
+                    let events = self.lifecycle.on_segment_event(segment_event);
+                    self.on_lifecycle_events(events);
+                }
+                let events = self.lifecycle.on_segment_state(self.sd.current_segment());
+                self.on_lifecycle_events(events);
+                // 正在延伸的线段作为高一级别的最后一笔，每次线段检测之后都要更新
+                self.recursive
+                    .on_segment_event(segment_event.as_ref(), self.sd.current_segment());
+                if let Some(segment_event) = segment_event {
+                    self.decomposer.on_segment_event(&segment_event);
+                    for pivot_event in self.spd.on_segment_event(&segment_event) {
+                        self.on_segment_pivot_event(pivot_event, bar.time);
+                    }
+                    let signals = self
+                        .segment_signal
+                        .on_segment_event(&segment_event, bar.time);
+                    self.on_signals(signals);
+                }
+            }
+        }
+        // 不再需要的MACD历史，不保留在内存和快照中
+        if let Some(time) = self.macd_horizon() {
+            self.macd.trim(time);
+        }
+    }
+
+    // 未完成的K线，在K线合并、分型和笔检测的副本上计算，不改变已经确认的状态
+    // 每次更新都从上一根完成的K线之后的状态重新计算，之前的临时结果自动作废
+    pub fn on_bar_update(&mut self, bar: &Bar) -> &[ProvisionalEvent] {
+        self.provisional.clear();
+        let mut fd = self.fd.snapshot();
+        let mut pd = self.pd.clone();
+        if let Some(f) = fd.on_new_bar(bar) {
+            self.provisional.push(ProvisionalEvent::Fractal(f.clone()));
+            if let Some(pen_event) = pd.on_new_fractal(f) {
+                self.provisional.push(ProvisionalEvent::Pen(pen_event));
             }
         }
+        &self.provisional
+    }
+
+    // K线完成，临时结果作废，按完成的K线处理
+    pub fn on_bar_close(&mut self, bar: &Bar) {
+        self.provisional.clear();
+        self.on_new_bar(bar);
+    }
+}
+
+// 与快照相同，订阅者和未完成K线的临时结果不属于分析状态，不会被复制
+impl Clone for Analyzer {
+    fn clone(&self) -> Self {
+        Self {
+            fd: self.fd.clone(),
+            pd: self.pd.clone(),
+            sd: self.sd.clone(),
+            ppd: self.ppd.clone(),
+            spd: self.spd.clone(),
+            pen_trend: self.pen_trend.clone(),
+            segment_trend: self.segment_trend.clone(),
+            decomposer: self.decomposer.clone(),
+            recursive: self.recursive.clone(),
+            macd: self.macd.clone(),
+            pen_divergence: self.pen_divergence.clone(),
+            segment_divergence: self.segment_divergence.clone(),
+            pen_consolidation_divergence: self.pen_consolidation_divergence.clone(),
+            segment_consolidation_divergence: self.segment_consolidation_divergence.clone(),
+            pen_signal: self.pen_signal.clone(),
+            segment_signal: self.segment_signal.clone(),
+            lifecycle: self.lifecycle.clone(),
+            candles: self.candles.clone(),
+            fractals: self.fractals.clone(),
+            points: self.points.clone(),
+            pens: self.pens.clone(),
+            segments: self.segments.clone(),
+            pivots: self.pivots.clone(),
+            trends: self.trends.clone(),
+            divergences: self.divergences.clone(),
+            consolidation_divergences: self.consolidation_divergences.clone(),
+            signals: self.signals.clone(),
+            provisional: Vec::new(),
+            lifecycle_events: self.lifecycle_events.clone(),
+            emitter: Emitter::new(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::macd::MacdArea;
+    use crate::sd2::SegmentDirection;
     use crate::test_util::tests::*;
+    use crate::trend::TrendType;
 
     #[test]
     fn test_analyzer() {
@@ -49,7 +624,254 @@ mod tests {
         for bar in &bars {
             analyzer.on_new_bar(bar);
         }
-        let count = analyzer.fractals.len();
-        println!("count = {}", count);
+        // 分型、笔和中枢
+        assert_eq!(analyzer.points.len(), analyzer.pens.len() + 1);
+        let pivots = analyzer.pivots();
+        assert!(!pivots.is_empty());
+        for pivot in pivots {
+            assert!(pivot.dd <= pivot.zd && pivot.zd < pivot.zg && pivot.zg <= pivot.gg);
+            assert!(pivot.start < pivot.end);
+        }
+
+        // 趋势中相邻的中枢没有重叠，盘整只有一个中枢
+        let trends = analyzer.trends();
+        assert!(!trends.is_empty());
+        for trend in trends {
+            match trend.trend_type {
+                TrendType::Consolidation => assert_eq!(trend.pivot_count(), 1),
+                TrendType::Up => assert!(trend.pivot_count() >= 2),
+                TrendType::Down => assert!(trend.pivot_count() >= 2),
+            }
+            assert!(trend.start() < trend.end());
+        }
+        let trend = analyzer.segment_trend().unwrap();
+        assert!(trend.pivot_count() >= 1);
+
+        // 相邻走势首尾相连时方向交替
+        let moves = analyzer.moves();
+        assert!(!moves.is_empty());
+        for m in moves {
+            match m.direction {
+                SegmentDirection::Up => assert!(m.end.price() > m.start.price()),
+                SegmentDirection::Down => assert!(m.end.price() < m.start.price()),
+            }
+        }
+        for w in moves.windows(2) {
+            assert!(w[0].end.time() <= w[1].start.time());
+            // 线段不连续时重新开始分解
+            if w[0].end.time() == w[1].start.time() {
+                assert_ne!(w[0].direction, w[1].direction);
+            }
+        }
+
+        // 离开段与进入段同向，创出新高(低)，力度小于进入段
+        let divergences = analyzer.divergences();
+        assert!(!divergences.is_empty());
+        for d in divergences {
+            let up = d.enter.is_up();
+            assert_eq!(d.leave.is_up(), up);
+            if up {
+                assert!(d.leave.high() > d.pivot.gg);
+            } else {
+                assert!(d.leave.low() < d.pivot.dd);
+            }
+            let (enter_sum, enter_peak) = d.enter_area.strength(up);
+            let (leave_sum, leave_peak) = d.leave_area.strength(up);
+            assert!(leave_sum < enter_sum || leave_peak < enter_peak);
+        }
+
+        // 盘整背驰的面积和斜率都小于前一段
+        let divergences = analyzer.consolidation_divergences();
+        assert!(!divergences.is_empty());
+        for d in divergences {
+            assert_eq!(d.prev.is_up(), d.leave.is_up());
+            assert!(d.area_ratio < 1.0 && d.slope_ratio < 1.0);
+            assert!(d.duration_ratio <= 2.0);
+        }
+
+        // 买卖点按时间顺序发出，三类买卖点都有
+        let signals = analyzer.signals();
+        let time = |s: &Signal| match s {
+            Signal::First(p) => p.time,
+            Signal::Second(p) => p.time,
+            Signal::Third(p) => p.time,
+            Signal::ThirdCancel(_, t) => *t,
+        };
+        assert!(signals.windows(2).all(|w| time(&w[0]) <= time(&w[1])));
+        assert!(signals.iter().any(|s| matches!(s, Signal::First(_))));
+        assert!(signals.iter().any(|s| matches!(s, Signal::Second(_))));
+        assert!(signals.iter().any(|s| matches!(s, Signal::Third(_))));
+
+        // 每一级的线段由低一级构成，数量更少
+        let recursive = analyzer.recursive();
+        assert!(recursive.level_count() >= 1);
+        let mut lower = analyzer.segments().len();
+        for level in 0..recursive.level_count() {
+            let segments = recursive.segments(level);
+            assert!(segments.is_empty() || segments.len() < lower);
+            for w in segments.windows(2) {
+                assert!(w[0].to().time() <= w[1].from().time());
+                assert!(w[0].is_up() != w[1].is_up() || w[0].to().time() < w[1].from().time());
+            }
+            lower = segments.len();
+        }
+        assert!(!recursive.segments(0).is_empty());
+    }
+
+    #[test]
+    fn test_macd_history() {
+        let bars = load_eurusd_2021();
+        let mut analyzer = Analyzer::new();
+        let mut macd = Macd::standard();
+        for bar in &bars {
+            analyzer.on_new_bar(bar);
+            macd.on_new_bar(bar);
+        }
+        // 早期的MACD已经丢弃
+        let area = analyzer.macd().area(bars[0].time, bars[1000].time);
+        assert_eq!(area.red + area.green, 0.0);
+
+        // 背驰用到的面积与保留完整历史时相同
+        let same = |a: &MacdArea, line: &Line| {
+            let b = macd.line_area(line);
+            a.red == b.red && a.green == b.green && a.red_peak == b.red_peak
+        };
+        assert!(analyzer
+            .divergences()
+            .iter()
+            .all(|d| same(&d.enter_area, &d.enter) && same(&d.leave_area, &d.leave)));
+        assert!(analyzer
+            .consolidation_divergences()
+            .iter()
+            .all(|d| same(&d.prev_area, &d.prev) && same(&d.leave_area, &d.leave)));
+    }
+
+    #[test]
+    fn test_query() {
+        let bars = load_eurusd_2021();
+        let mut analyzer = Analyzer::new();
+        for bar in &bars {
+            analyzer.on_new_bar(bar);
+        }
+        let pens = analyzer.pens();
+        assert!(pens.len() > 2);
+        assert!(pens.windows(2).all(|w| w[0].id < w[1].id));
+        assert!(pens
+            .windows(2)
+            .all(|w| w[0].value.to().time() == w[1].value.from().time()));
+        // 只有最后一笔尚未确认
+        assert!(pens[..pens.len() - 1].iter().all(|p| p.confirmed));
+        assert!(!pens.last().unwrap().confirmed);
+        assert_eq!(analyzer.pen_points().len(), pens.len() + 1);
+        // 笔的端点都来自分型检测
+        let fractals = analyzer.fractals();
+        assert!(fractals.len() > analyzer.pen_points().len());
+        assert!(fractals.windows(2).all(|w| w[0].id < w[1].id));
+        assert!(fractals
+            .windows(2)
+            .all(|w| w[0].value.time() < w[1].value.time()));
+        assert!(fractals.iter().all(|f| f.confirmed));
+        assert!(analyzer.pen_points().iter().all(|p| fractals
+            .binary_search_by_key(&p.value.time(), |f| f.value.time())
+            .is_ok()));
+        let candles = analyzer.candles();
+        assert!(candles.len() <= bars.len());
+        assert!(candles.windows(2).all(|w| w[0].id < w[1].id));
+        assert!(candles.iter().all(|c| c.confirmed));
+
+        let last = analyzer.last_confirmed_pen().unwrap();
+        assert_eq!(last.id, pens[pens.len() - 2].id);
+        assert_eq!(analyzer.pen(last.id).unwrap().id, last.id);
+        let (from, to) = (last.value.from().time(), last.value.to().time());
+        assert_eq!(analyzer.pen_at(from + 1).unwrap().id, last.id);
+        assert_eq!(analyzer.pen_at(to).unwrap().id, last.id);
+        assert!(analyzer.pen_at(pens[0].value.from().time() - 1).is_none());
+
+        let segment = analyzer.last_confirmed_segment().unwrap();
+        let time = segment.value.to().time();
+        assert_eq!(analyzer.segment_containing(time).unwrap().id, segment.id);
+        assert!(analyzer.segments().windows(2).all(|w| w[0].id < w[1].id));
+    }
+
+    #[test]
+    fn test_subscribe() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let bars = load_eurusd_2021();
+        let mut analyzer = Analyzer::new();
+        let all = Rc::new(RefCell::new(Vec::new()));
+        let pens = Rc::new(RefCell::new(0));
+        let events = all.clone();
+        let id = analyzer.subscribe(move |e| events.borrow_mut().push(e.kind()));
+        let count = pens.clone();
+        analyzer.subscribe_kinds(&[EventKind::Pen], move |e| {
+            assert_eq!(e.kind(), EventKind::Pen);
+            *count.borrow_mut() += 1;
+        });
+        for bar in &bars {
+            analyzer.on_new_bar(bar);
+        }
+
+        let kinds = all.borrow();
+        let count = |kind| kinds.iter().filter(|k| **k == kind).count();
+        assert_eq!(count(EventKind::Candle), analyzer.candles().len());
+        assert_eq!(count(EventKind::Signal), analyzer.signals().len());
+        assert_eq!(count(EventKind::Divergence), analyzer.divergences().len());
+        assert_eq!(
+            count(EventKind::ConsolidationDivergence),
+            analyzer.consolidation_divergences().len()
+        );
+        // 只有与前一根K线合并的K线才会发出更新事件
+        let updates = count(EventKind::CandleUpdate);
+        assert!(updates > 0 && updates < bars.len() - analyzer.candles().len());
+        assert_eq!(count(EventKind::Pen), *pens.borrow());
+        assert!(count(EventKind::Segment) > 0);
+
+        assert!(analyzer.unsubscribe(id));
+        let total = kinds.len();
+        drop(kinds);
+        analyzer.on_new_bar(&bars[0]);
+        assert_eq!(total, all.borrow().len());
+    }
+
+    #[test]
+    fn test_bar_update() {
+        let mut analyzer = Analyzer::new();
+        let bars = [(2.0, 1.0), (3.0, 2.0), (4.0, 3.0), (3.5, 2.5)];
+        for (i, (high, low)) in bars.iter().enumerate() {
+            analyzer.on_bar_close(&Bar::new(i as i64, *low, *high, *low, *high));
+        }
+
+        // 未完成的K线与前一根不包含，确认顶分型
+        let events = analyzer.on_bar_update(&Bar::new(4, 3.0, 3.0, 2.0, 2.0));
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], ProvisionalEvent::Fractal(f) if f.price() == 4.0));
+
+        // 更新后被前一根包含，分型不成立，之前的临时结果作废
+        let events = analyzer.on_bar_update(&Bar::new(4, 3.0, 3.4, 2.6, 3.2));
+        assert!(events.is_empty());
+
+        analyzer.on_bar_close(&Bar::new(4, 3.0, 3.4, 2.6, 3.2));
+        assert!(analyzer.provisional().is_empty());
+    }
+
+    #[test]
+    fn test_bar_update_no_side_effect() {
+        let bars = load_eurusd_2021();
+        let mut expected = Analyzer::new();
+        let mut analyzer = Analyzer::new();
+        for bar in &bars {
+            expected.on_new_bar(bar);
+            let mut forming = bar.clone();
+            forming.high += 0.001;
+            analyzer.on_bar_update(&forming);
+            analyzer.on_bar_update(bar);
+            analyzer.on_bar_close(bar);
+        }
+        assert_eq!(analyzer.pivots().len(), expected.pivots().len());
+        assert_eq!(analyzer.divergences().len(), expected.divergences().len());
+        assert_eq!(analyzer.signals().len(), expected.signals().len());
+        assert_eq!(analyzer.moves().len(), expected.moves().len());
     }
 }
@@ -2,12 +2,16 @@ use crate::time::Time;
 
 // 未经过包含处理的K线
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bar {
     pub time: Time,
     pub open: f64,
     pub high: f64,
     pub low: f64,
     pub close: f64,
+    // 成交量，没有成交量的数据为0
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub volume: f64,
 }
 
 impl Bar {
@@ -18,6 +22,12 @@ impl Bar {
             high,
             low,
             close,
+            volume: 0.0,
         }
     }
+
+    pub fn with_volume(mut self, volume: f64) -> Self {
+        self.volume = volume;
+        self
+    }
 }
@@ -0,0 +1,563 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+
+use rzen::analyzer::Analyzer;
+use rzen::bar::Bar;
+use rzen::fractal::{Fractal, FractalType};
+use rzen::lifecycle::Tracked;
+use rzen::pen_detector::PenRule;
+use rzen::pivot::Pivot;
+use rzen::time::Time;
+
+// 批量分析K线文件，输出包含处理之后的K线、笔的端点、笔、线段和中枢
+// 例：rzen analyze bars.csv --timezone Asia/Shanghai --format jsonl | jq 'select(.type == "pen")'
+// 输出的时间都是毫秒数
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(name = "rzen", version, about = "缠论K线分析")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 分析K线文件
+    Analyze(AnalyzeArgs),
+}
+
+#[derive(Parser)]
+struct AnalyzeArgs {
+    /// K线文件，-表示标准输入
+    input: PathBuf,
+
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+
+    /// 输出文件，默认为标准输出
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// 输出的对象，逗号分隔
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "candles,fractals,pens,segments,pivots"
+    )]
+    objects: Vec<Kind>,
+
+    /// 时间的列名或者从0开始的列序号，默认按列名time/datetime/date/timestamp查找
+    #[arg(long)]
+    time_column: Option<String>,
+    /// 开盘价的列名或者列序号
+    #[arg(long, default_value = "open")]
+    open_column: String,
+    /// 最高价的列名或者列序号
+    #[arg(long, default_value = "high")]
+    high_column: String,
+    /// 最低价的列名或者列序号
+    #[arg(long, default_value = "low")]
+    low_column: String,
+    /// 收盘价的列名或者列序号
+    #[arg(long, default_value = "close")]
+    close_column: String,
+
+    /// 文件没有表头，列只能用序号指定
+    #[arg(long)]
+    no_header: bool,
+
+    /// 列分隔符
+    #[arg(long, default_value_t = ',')]
+    delimiter: char,
+
+    /// 时间格式：ms、s或者strftime格式，默认自动识别毫秒数、RFC3339和常见的日期时间格式
+    #[arg(long)]
+    time_format: Option<String>,
+
+    /// 不带时区的时间所在的时区，IANA名称(Asia/Shanghai)或者偏移(+08:00)
+    #[arg(long, default_value = "UTC")]
+    timezone: String,
+
+    /// 笔的划分规则
+    #[arg(long, value_enum, default_value_t = Rule::Standard)]
+    pen_rule: Rule,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Json,
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Kind {
+    Candles,
+    Fractals,
+    /// 笔的端点
+    PenPoints,
+    Pens,
+    Segments,
+    Pivots,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Rule {
+    /// 老笔，顶底分型之间至少有一根独立K线
+    Standard,
+    /// 新笔，顶底分型不共用K线
+    Relaxed,
+}
+
+impl From<Rule> for PenRule {
+    fn from(rule: Rule) -> Self {
+        match rule {
+            Rule::Standard => PenRule::Standard,
+            Rule::Relaxed => PenRule::Relaxed,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Zone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Zone {
+    fn parse(s: &str) -> Result<Self> {
+        if let Ok(tz) = s.parse::<Tz>() {
+            return Ok(Zone::Named(tz));
+        }
+        let offset = DateTime::parse_from_str(&format!("2000-01-01 00:00:00 {}", s), "%F %T %:z")
+            .map_err(|_| format!("invalid timezone: {}", s))?;
+        Ok(Zone::Fixed(offset.offset().fix()))
+    }
+
+    fn timestamp(&self, dt: &NaiveDateTime) -> Result<Time> {
+        let time = match self {
+            Zone::Fixed(offset) => offset
+                .from_local_datetime(dt)
+                .single()
+                .map(|t| t.timestamp_millis()),
+            Zone::Named(tz) => tz
+                .from_local_datetime(dt)
+                .earliest()
+                .map(|t| t.timestamp_millis()),
+        };
+        time.ok_or_else(|| format!("invalid local time: {}", dt).into())
+    }
+}
+
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y.%m.%d %H:%M",
+];
+
+struct TimeParser {
+    format: Option<String>,
+    zone: Zone,
+}
+
+impl TimeParser {
+    fn parse(&self, s: &str) -> Result<Time> {
+        let s = s.trim();
+        match self.format.as_deref() {
+            Some("ms") => return Ok(s.parse()?),
+            Some("s") => return Ok(s.parse::<i64>()? * 1000),
+            Some(format) => {
+                return match DateTime::parse_from_str(s, format) {
+                    Ok(dt) => Ok(dt.timestamp_millis()),
+                    Err(_) => self
+                        .zone
+                        .timestamp(&NaiveDateTime::parse_from_str(s, format)?),
+                }
+            }
+            None => {}
+        }
+
+        if let Ok(ms) = s.parse::<i64>() {
+            return Ok(ms);
+        }
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Ok(dt.timestamp_millis());
+        }
+        for format in DATETIME_FORMATS.iter() {
+            if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+                return self.zone.timestamp(&dt);
+            }
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return self.zone.timestamp(&date.and_hms_opt(0, 0, 0).unwrap());
+        }
+        Err(format!("invalid time: {}", s).into())
+    }
+}
+
+// 列名或者列序号
+fn column(headers: Option<&csv::StringRecord>, names: &[&str]) -> Result<usize> {
+    if let Ok(index) = names[0].parse::<usize>() {
+        return Ok(index);
+    }
+    let headers = headers.ok_or("columns must be indices when there is no header")?;
+    names
+        .iter()
+        .find_map(|name| {
+            headers
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+        })
+        .ok_or_else(|| format!("column not found: {}", names.join("/")).into())
+}
+
+fn load_bars(args: &AnalyzeArgs) -> Result<Vec<Bar>> {
+    let input: Box<dyn io::Read> = if args.input.as_os_str() == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(&args.input)?)
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(!args.no_header)
+        .delimiter(args.delimiter as u8)
+        .from_reader(input);
+    let headers = if args.no_header {
+        None
+    } else {
+        Some(reader.headers()?.clone())
+    };
+    let headers = headers.as_ref();
+
+    let time_names = match &args.time_column {
+        Some(name) => vec![name.as_str()],
+        None => vec!["time", "datetime", "date", "timestamp"],
+    };
+    let columns = [
+        column(headers, &time_names)?,
+        column(headers, &[&args.open_column])?,
+        column(headers, &[&args.high_column])?,
+        column(headers, &[&args.low_column])?,
+        column(headers, &[&args.close_column])?,
+    ];
+    let parser = TimeParser {
+        format: args.time_format.clone(),
+        zone: Zone::parse(&args.timezone)?,
+    };
+
+    let mut bars = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        let field = |i: usize| -> Result<&str> {
+            record
+                .get(columns[i])
+                .ok_or_else(|| format!("line {}: missing column {}", line + 1, columns[i]).into())
+        };
+        let price = |i: usize| -> Result<f64> {
+            field(i)?
+                .trim()
+                .parse()
+                .map_err(|e| format!("line {}: {}", line + 1, e).into())
+        };
+        let time = parser
+            .parse(field(0)?)
+            .map_err(|e| format!("line {}: {}", line + 1, e))?;
+        bars.push(Bar::new(time, price(1)?, price(2)?, price(3)?, price(4)?));
+    }
+    Ok(bars)
+}
+
+// 所有输出格式共用的记录，CSV中不适用的字段为空
+#[derive(Serialize, Default)]
+struct Record {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    // K线、分型、笔的端点的时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<Time>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    open: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    high: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    low: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    close: Option<f64>,
+    // 分型的价格
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<f64>,
+    // top或者bottom
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fractal: Option<&'static str>,
+    // 笔、线段的起点和终点，中枢的开始和结束时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_time: Option<Time>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_time: Option<Time>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_price: Option<f64>,
+    // 中枢的级别：0为笔中枢，1为线段中枢，更高的级别依次增加
+    #[serde(skip_serializing_if = "Option::is_none")]
+    level: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zg: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gg: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confirmed: Option<bool>,
+}
+
+fn fractal_record(kind: &'static str, f: &Tracked<Fractal>) -> Record {
+    Record {
+        kind,
+        id: Some(f.id),
+        time: Some(f.value.time()),
+        price: Some(f.value.price()),
+        fractal: Some(match f.value.fractal_type() {
+            FractalType::Top => "top",
+            FractalType::Bottom => "bottom",
+        }),
+        confirmed: Some(f.confirmed),
+        ..Default::default()
+    }
+}
+
+fn pivot_record(pivot: &Pivot, confirmed: bool) -> Record {
+    Record {
+        kind: "pivot",
+        from_time: Some(pivot.start),
+        to_time: Some(pivot.end),
+        level: Some(pivot.level.as_index()),
+        zg: Some(pivot.zg),
+        zd: Some(pivot.zd),
+        gg: Some(pivot.gg),
+        dd: Some(pivot.dd),
+        count: Some(pivot.count),
+        confirmed: Some(confirmed),
+        ..Default::default()
+    }
+}
+
+fn records(analyzer: &Analyzer, kind: Kind) -> Vec<Record> {
+    match kind {
+        Kind::Candles => analyzer
+            .candles()
+            .iter()
+            .map(|bar| Record {
+                kind: "candle",
+                id: Some(bar.id),
+                time: Some(bar.value.time),
+                open: Some(bar.value.open),
+                high: Some(bar.value.high),
+                low: Some(bar.value.low),
+                close: Some(bar.value.close),
+                confirmed: Some(bar.confirmed),
+                ..Default::default()
+            })
+            .collect(),
+        Kind::Fractals => analyzer
+            .fractals()
+            .iter()
+            .map(|f| fractal_record("fractal", f))
+            .collect(),
+        Kind::PenPoints => analyzer
+            .pen_points()
+            .iter()
+            .map(|f| fractal_record("pen_point", f))
+            .collect(),
+        Kind::Pens | Kind::Segments => {
+            let (name, lines) = match kind {
+                Kind::Pens => ("pen", analyzer.pens()),
+                _ => ("segment", analyzer.segments()),
+            };
+            lines
+                .iter()
+                .map(|l| Record {
+                    kind: name,
+                    id: Some(l.id),
+                    from_time: Some(l.value.from().time()),
+                    from_price: Some(l.value.from().price()),
+                    to_time: Some(l.value.to().time()),
+                    to_price: Some(l.value.to().price()),
+                    confirmed: Some(l.confirmed),
+                    ..Default::default()
+                })
+                .collect()
+        }
+        // 已经结束的中枢在前，正在延伸的笔中枢和线段中枢在后
+        Kind::Pivots => analyzer
+            .pivots()
+            .iter()
+            .map(|p| pivot_record(p, true))
+            .chain(analyzer.pen_pivot().map(|p| pivot_record(p, false)))
+            .chain(analyzer.segment_pivot().map(|p| pivot_record(p, false)))
+            .collect(),
+    }
+}
+
+// CSV的每一行都有所有的列，不能跳过空字段
+#[derive(Serialize)]
+struct CsvRecord<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    id: Option<u64>,
+    time: Option<Time>,
+    open: Option<f64>,
+    high: Option<f64>,
+    low: Option<f64>,
+    close: Option<f64>,
+    price: Option<f64>,
+    fractal: Option<&'a str>,
+    from_time: Option<Time>,
+    from_price: Option<f64>,
+    to_time: Option<Time>,
+    to_price: Option<f64>,
+    level: Option<u32>,
+    zg: Option<f64>,
+    zd: Option<f64>,
+    gg: Option<f64>,
+    dd: Option<f64>,
+    count: Option<usize>,
+    confirmed: Option<bool>,
+}
+
+impl<'a> From<&'a Record> for CsvRecord<'a> {
+    fn from(r: &'a Record) -> Self {
+        Self {
+            kind: r.kind,
+            id: r.id,
+            time: r.time,
+            open: r.open,
+            high: r.high,
+            low: r.low,
+            close: r.close,
+            price: r.price,
+            fractal: r.fractal,
+            from_time: r.from_time,
+            from_price: r.from_price,
+            to_time: r.to_time,
+            to_price: r.to_price,
+            level: r.level,
+            zg: r.zg,
+            zd: r.zd,
+            gg: r.gg,
+            dd: r.dd,
+            count: r.count,
+            confirmed: r.confirmed,
+        }
+    }
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Candles => "candles",
+        Kind::Fractals => "fractals",
+        Kind::PenPoints => "pen_points",
+        Kind::Pens => "pens",
+        Kind::Segments => "segments",
+        Kind::Pivots => "pivots",
+    }
+}
+
+fn write_output(analyzer: &Analyzer, args: &AnalyzeArgs, out: &mut dyn Write) -> Result<()> {
+    match args.format {
+        // {"candles": [...], "pens": [...], ...}
+        Format::Json => {
+            write!(out, "{{")?;
+            for (i, kind) in args.objects.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                write!(out, "\"{}\":", kind_name(*kind))?;
+                serde_json::to_writer(&mut *out, &records(analyzer, *kind))?;
+            }
+            writeln!(out, "}}")?;
+        }
+        Format::Jsonl => {
+            for kind in &args.objects {
+                for record in records(analyzer, *kind) {
+                    serde_json::to_writer(&mut *out, &record)?;
+                    writeln!(out)?;
+                }
+            }
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            for kind in &args.objects {
+                for record in records(analyzer, *kind) {
+                    writer.serialize(CsvRecord::from(&record))?;
+                }
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn analyze(args: &AnalyzeArgs) -> Result<()> {
+    let bars = load_bars(args)?;
+    let mut analyzer = Analyzer::with_rule(args.pen_rule.into());
+    for bar in &bars {
+        analyzer.on_new_bar(bar);
+    }
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    write_output(&analyzer, args, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+// 输出到管道时下游提前退出，例如rzen analyze ... | head
+fn is_broken_pipe(err: &(dyn Error + 'static)) -> bool {
+    let kind = if let Some(e) = err.downcast_ref::<io::Error>() {
+        Some(e.kind())
+    } else if let Some(e) = err.downcast_ref::<serde_json::Error>() {
+        e.io_error_kind()
+    } else if let Some(e) = err.downcast_ref::<csv::Error>() {
+        match e.kind() {
+            csv::ErrorKind::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    kind == Some(io::ErrorKind::BrokenPipe)
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Analyze(args) => analyze(args),
+    };
+    if let Err(err) = result {
+        if is_broken_pipe(err.as_ref()) {
+            return;
+        }
+        eprintln!("rzen: {}", err);
+        std::process::exit(1);
+    }
+}
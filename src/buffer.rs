@@ -0,0 +1,425 @@
+use crate::analyzer::Analyzer;
+use crate::bar::Bar;
+use crate::fractal::FractalType;
+use crate::lifecycle::Tracked;
+use crate::line::Line;
+use crate::pivot::{Pivot, PivotLevel};
+use crate::time::Time;
+
+// MT4/5风格的指标缓冲区
+// 输出数组与输入K线按下标对齐，没有值的位置为EMPTY_VALUE
+// 最后一根K线视为未完成，下一根K线出现之后才输入Analyzer
+
+// 与MQL的EMPTY_VALUE(DBL_MAX)相同
+pub const EMPTY_VALUE: f64 = f64::MAX;
+
+// 每输入这么多根K线保存一个检查点，最多保留最近的几个
+const CHECKPOINT_INTERVAL: usize = 500;
+const MAX_CHECKPOINTS: usize = 4;
+
+// 输入的K线，各数组长度相同，按时间从旧到新排列
+pub struct Rates<'a> {
+    pub time: &'a [Time],
+    pub open: &'a [f64],
+    pub high: &'a [f64],
+    pub low: &'a [f64],
+    pub close: &'a [f64],
+}
+
+impl<'a> Rates<'a> {
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    fn is_aligned(&self) -> bool {
+        let len = self.len();
+        [self.open, self.high, self.low, self.close]
+            .iter()
+            .all(|column| column.len() == len)
+    }
+
+    fn bar(&self, index: usize) -> Bar {
+        Bar::new(
+            self.time[index],
+            self.open[index],
+            self.high[index],
+            self.low[index],
+            self.close[index],
+        )
+    }
+}
+
+// 输出缓冲区，长度与输入K线数量相同
+pub struct Buffers<'a> {
+    // 顶分型的最高价
+    pub fractal_top: &'a mut [f64],
+    // 底分型的最低价
+    pub fractal_bottom: &'a mut [f64],
+    // 笔的端点，用DRAW_ZIGZAG/DRAW_SECTION连接
+    pub pen: &'a mut [f64],
+    // 线段的端点
+    pub segment: &'a mut [f64],
+    // 中枢的上沿ZG和下沿ZD，中枢持续的每根K线都有值
+    pub pivot_high: &'a mut [f64],
+    pub pivot_low: &'a mut [f64],
+}
+
+impl<'a> Buffers<'a> {
+    // 所有缓冲区的长度都是len
+    fn has_len(&self, len: usize) -> bool {
+        [
+            self.fractal_top.len(),
+            self.fractal_bottom.len(),
+            self.pen.len(),
+            self.segment.len(),
+            self.pivot_high.len(),
+            self.pivot_low.len(),
+        ]
+        .iter()
+        .all(|l| *l == len)
+    }
+
+    fn clear(&mut self, from: usize) {
+        for buffer in [
+            &mut *self.fractal_top,
+            &mut *self.fractal_bottom,
+            &mut *self.pen,
+            &mut *self.segment,
+            &mut *self.pivot_high,
+            &mut *self.pivot_low,
+        ] {
+            for value in buffer[from..].iter_mut() {
+                *value = EMPTY_VALUE;
+            }
+        }
+    }
+}
+
+// 输入index根K线之后的状态，之后的K线发生变化时从这里重新计算
+#[derive(Debug)]
+struct Checkpoint {
+    index: usize,
+    analyzer: Analyzer,
+    stable: usize,
+}
+
+#[derive(Debug)]
+pub struct BufferIndicator {
+    // pivot_high/pivot_low显示的中枢级别
+    level: PivotLevel,
+    analyzer: Analyzer,
+    // 已经输入Analyzer的K线时间
+    times: Vec<Time>,
+    // 该下标之前的输出不会再变化
+    stable: usize,
+    // 按index从小到大排列
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl BufferIndicator {
+    // 只支持笔中枢和线段中枢，其他级别返回None
+    pub fn new(level: PivotLevel) -> Option<Self> {
+        if !matches!(level, PivotLevel::Pen | PivotLevel::Segment) {
+            return None;
+        }
+        Some(Self {
+            level,
+            analyzer: Analyzer::new(),
+            times: Vec::new(),
+            stable: 0,
+            checkpoints: Vec::new(),
+        })
+    }
+
+    fn reset(&mut self) {
+        self.analyzer = Analyzer::new();
+        self.times.clear();
+        self.stable = 0;
+        self.checkpoints.clear();
+    }
+
+    fn checkpoint(&mut self) {
+        if self.checkpoints.len() == MAX_CHECKPOINTS {
+            self.checkpoints.remove(0);
+        }
+        self.checkpoints.push(Checkpoint {
+            index: self.times.len(),
+            analyzer: self.analyzer.clone(),
+            stable: self.stable_index(),
+        });
+    }
+
+    // 回到first之前最近的检查点，没有这样的检查点时从头计算
+    // 返回输出可能发生变化的起始下标
+    fn rollback(&mut self, first: usize) -> usize {
+        while self.checkpoints.last().is_some_and(|c| c.index > first) {
+            self.checkpoints.pop();
+        }
+        match self.checkpoints.last() {
+            Some(checkpoint) => {
+                self.analyzer = checkpoint.analyzer.clone();
+                self.times.truncate(checkpoint.index);
+                self.stable = checkpoint.stable;
+            }
+            None => self.reset(),
+        }
+        self.stable
+    }
+
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    // K线时间对应的下标，时间不存在时取之前最近的K线
+    fn index(&self, time: Time) -> usize {
+        self.times.partition_point(|t| *t <= time).max(1) - 1
+    }
+
+    // 与MQL的OnCalculate相同，prev_calculated为上一次调用返回的K线数量，0表示全部重新计算
+    // prev_calculated之前的K线没有变化，已经输入Analyzer的K线发生变化时从之前最近的检查点重新计算
+    // 返回(本次计算的K线数量, 输出发生变化的起始下标)
+    pub fn calculate(
+        &mut self,
+        rates: &Rates,
+        prev_calculated: usize,
+        buffers: &mut Buffers,
+    ) -> (usize, usize) {
+        let total = rates.len();
+        assert!(rates.is_aligned(), "rates columns have different lengths");
+        assert!(buffers.has_len(total), "buffer length differs from rates");
+        let fed = self.times.len();
+        // 第一根发生变化的已输入K线，历史数据发生了移动时从头计算
+        let mut first = if prev_calculated == 0 || prev_calculated > total {
+            0
+        } else {
+            prev_calculated.min(fed)
+        };
+        if first > 0 && rates.time[first - 1] != self.times[first - 1] {
+            first = 0;
+        }
+        let from = if first < fed {
+            self.rollback(first)
+        } else {
+            self.stable
+        };
+
+        for i in self.times.len()..total.saturating_sub(1) {
+            if i > 0 && i % CHECKPOINT_INTERVAL == 0 {
+                self.checkpoint();
+            }
+            self.analyzer.on_new_bar(&rates.bar(i));
+            self.times.push(rates.time[i]);
+        }
+
+        buffers.clear(from);
+        self.draw(from, buffers);
+        self.stable = self.stable_index();
+        (total, from)
+    }
+
+    // 绘制从from开始的输出
+    fn draw(&self, from: usize, buffers: &mut Buffers) {
+        let time = match self.times.get(from) {
+            Some(time) => *time,
+            None => return,
+        };
+
+        let fractals = self.analyzer.fractals();
+        let start = fractals.partition_point(|f| f.value.time() < time);
+        for f in &fractals[start..] {
+            let i = self.index(f.value.time());
+            match f.value.fractal_type() {
+                FractalType::Top => buffers.fractal_top[i] = f.value.highest(),
+                FractalType::Bottom => buffers.fractal_bottom[i] = f.value.lowest(),
+            }
+        }
+
+        let points = |lines: &[Tracked<Line>], buffer: &mut [f64]| {
+            for line in lines.iter().filter(|l| l.value.to().time() >= time) {
+                for point in &[line.value.from(), line.value.to()] {
+                    if point.time() >= time {
+                        buffer[self.index(point.time())] = point.price();
+                    }
+                }
+            }
+        };
+        points(self.analyzer.pens(), buffers.pen);
+        points(self.analyzer.segments(), buffers.segment);
+
+        for pivot in self.pivots().filter(|p| p.end >= time) {
+            let start = self.index(pivot.start).max(from);
+            for i in start..=self.index(pivot.end) {
+                buffers.pivot_high[i] = pivot.zg;
+                buffers.pivot_low[i] = pivot.zd;
+            }
+        }
+    }
+
+    // 已经结束的中枢和正在延伸的中枢
+    fn pivots(&self) -> impl Iterator<Item = &Pivot> {
+        let current = if self.level == PivotLevel::Pen {
+            self.analyzer.pen_pivot()
+        } else {
+            self.analyzer.segment_pivot()
+        };
+        let level = self.level;
+        self.analyzer
+            .pivots()
+            .iter()
+            .filter(move |p| p.level == level)
+            .chain(current)
+    }
+
+    // 之后的K线只可能改变这个下标之后的输出
+    // 最后一笔的终点可能被撤销，新线段从最后确认的线段之后开始
+    // 新的中枢由最近的4笔(线段)构成，正在延伸的中枢会继续变化
+    fn stable_index(&self) -> usize {
+        let back = |lines: &[Tracked<Line>], n: usize| {
+            let confirmed = lines.iter().rposition(|l| l.confirmed);
+            match confirmed {
+                Some(i) if i >= n => self.index(lines[i - n].value.from().time()),
+                _ => 0,
+            }
+        };
+        let mut stable = back(self.analyzer.pens(), 4).min(back(self.analyzer.segments(), 4));
+        if let Some(pivot) = self.analyzer.pen_pivot() {
+            stable = stable.min(self.index(pivot.start));
+        }
+        if let Some(pivot) = self.analyzer.segment_pivot() {
+            stable = stable.min(self.index(pivot.start));
+        }
+        stable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::*;
+
+    struct Output {
+        values: Vec<Vec<f64>>,
+    }
+
+    impl Output {
+        fn new(len: usize) -> Self {
+            Self {
+                values: vec![vec![EMPTY_VALUE; len]; 6],
+            }
+        }
+
+        fn resize(&mut self, len: usize) {
+            for v in self.values.iter_mut() {
+                v.resize(len, EMPTY_VALUE);
+            }
+        }
+
+        fn buffers(&mut self) -> Buffers<'_> {
+            let [a, b, c, d, e, f] = &mut self.values[..] else {
+                unreachable!()
+            };
+            Buffers {
+                fractal_top: a,
+                fractal_bottom: b,
+                pen: c,
+                segment: d,
+                pivot_high: e,
+                pivot_low: f,
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental() {
+        let bars = &load_eurusd_2021()[..6000];
+        let column = |f: fn(&Bar) -> f64| bars.iter().map(f).collect::<Vec<f64>>();
+        let time: Vec<Time> = bars.iter().map(|b| b.time).collect();
+        let (open, high, low, close) = (
+            column(|b| b.open),
+            column(|b| b.high),
+            column(|b| b.low),
+            column(|b| b.close),
+        );
+        let rates = |n: usize| Rates {
+            time: &time[..n],
+            open: &open[..n],
+            high: &high[..n],
+            low: &low[..n],
+            close: &close[..n],
+        };
+
+        let mut indicator = BufferIndicator::new(PivotLevel::Pen).unwrap();
+        let mut output = Output::new(0);
+        let mut prev = 0;
+        for n in 1..=bars.len() {
+            output.resize(n);
+            let (calculated, from) = indicator.calculate(&rates(n), prev, &mut output.buffers());
+            assert!(from <= n);
+            prev = calculated;
+
+            // 增量计算与从头计算的结果相同
+            if n % 1500 == 0 {
+                let mut expected = Output::new(n);
+                let mut full = BufferIndicator::new(PivotLevel::Pen).unwrap();
+                full.calculate(&rates(n), 0, &mut expected.buffers());
+                assert_eq!(output.values, expected.values);
+            }
+        }
+
+        let pens = output.values[2]
+            .iter()
+            .filter(|v| **v != EMPTY_VALUE)
+            .count();
+        assert_eq!(pens, indicator.analyzer().pens().len() + 1);
+        assert!(output.values[4].iter().any(|v| *v != EMPTY_VALUE));
+        // 最后一根K线尚未输入Analyzer
+        assert_eq!(indicator.times.len(), bars.len() - 1);
+
+        // 已经输入的K线发生变化，从之前最近的检查点重新计算，结果与从头计算相同
+        let n = bars.len();
+        let mut changed = high.clone();
+        changed[n - 100] += 0.01;
+        let changed = Rates {
+            high: &changed,
+            ..rates(n)
+        };
+        let (_, from) = indicator.calculate(&changed, n - 100, &mut output.buffers());
+        assert!(from > 0 && from <= n - 100);
+        let mut expected = Output::new(n);
+        let mut full = BufferIndicator::new(PivotLevel::Pen).unwrap();
+        full.calculate(&changed, 0, &mut expected.buffers());
+        assert_eq!(output.values, expected.values);
+
+        // 第一个检查点之前的K线发生变化，从头计算
+        let (_, from) = indicator.calculate(&changed, 10, &mut output.buffers());
+        assert_eq!(from, 0);
+        assert_eq!(output.values, expected.values);
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer length")]
+    fn test_buffer_length() {
+        let (time, price) = ([0, 1], [1.0, 2.0]);
+        let rates = Rates {
+            time: &time,
+            open: &price,
+            high: &price,
+            low: &price,
+            close: &price,
+        };
+        let mut output = Output::new(2);
+        output.values[5].truncate(1);
+        let mut indicator = BufferIndicator::new(PivotLevel::Pen).unwrap();
+        indicator.calculate(&rates, 0, &mut output.buffers());
+    }
+
+    #[test]
+    fn test_level() {
+        assert!(BufferIndicator::new(PivotLevel::Segment).is_some());
+        assert!(BufferIndicator::new(PivotLevel::Higher(1)).is_none());
+    }
+}
@@ -2,12 +2,14 @@ use crate::bar::Bar;
 use crate::time::Time;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Direction {
     Up,
     Down,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Candle {
     // index的作用是为了计算Candle之间的距离，严格笔要求分型之间有5根K，通过index2 - index1就很容易检测是否满足条件，而无需保存整个Candle序列
     // 检测到分型的时候，分型的index就是分型中间Candle的index
@@ -56,6 +58,8 @@ impl Candle {
         if (current.bar.high >= bar.high && current.bar.low <= bar.low)
             || (current.bar.high <= bar.high && current.bar.low >= bar.low)
         {
+            // 合并后的成交量为所有K线成交量之和
+            current.bar.volume += bar.volume;
             // 特殊的一字板与前一根K高低点相同情况的处理
             let high_eq_low = bar.high == bar.low; // 一字板
 
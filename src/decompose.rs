@@ -0,0 +1,251 @@
+use crate::fractal::Fractal;
+use crate::line::Line;
+use crate::pivot::{Pivot, PivotDetector, PivotEvent, PivotLevel};
+use crate::sd2::{SegmentDirection, SegmentEvent};
+
+// 同级别分解
+// 以线段为次级别，把历史走势分解为首尾相接、互不重叠的同级别走势
+// 规则：
+// 1. 每个走势至少由三个线段构成，走势的终点是走势方向上的极值点
+// 2. 极值点之后出现反向走势，且反向走势到其极值点已经有三个线段，原走势才算结束
+// 3. 反向走势形成之前，原走势再创新高(低)，则原走势延续
+// 由于走势只有在后一个走势至少三个线段之后才确认，已经确认的走势不会再改变
+// 第一个走势的起点是任意的，如果不足三个线段，直接丢弃
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Move {
+    pub start: Fractal,
+    pub end: Fractal,
+    pub direction: SegmentDirection,
+    // 走势中的线段中枢
+    pub pivots: Vec<Pivot>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decomposer {
+    // 当前走势起点开始的所有线段端点
+    points: Vec<Fractal>,
+    direction: Option<SegmentDirection>,
+    // 进入当前走势的线段，即前一个走势的最后一个线段
+    enter: Option<Line>,
+    moves: Vec<Move>,
+}
+
+impl Decomposer {
+    pub fn new() -> Self {
+        Self {
+            points: Vec::new(),
+            direction: None,
+            enter: None,
+            moves: Vec::new(),
+        }
+    }
+
+    // 已经确认的走势
+    pub fn moves(&self) -> &Vec<Move> {
+        &self.moves
+    }
+
+    // 当前尚未确认的走势
+    pub fn current(&self) -> Option<Move> {
+        let direction = self.direction?;
+        let end = self.find_extreme(direction);
+        Some(self.make_move(end, direction))
+    }
+
+    fn is_beyond(direction: SegmentDirection, price: f64, other: f64) -> bool {
+        match direction {
+            SegmentDirection::Up => price > other,
+            SegmentDirection::Down => price < other,
+        }
+    }
+
+    // 走势方向上的极值点，与起点类型相反的端点位于奇数位置
+    fn find_extreme(&self, direction: SegmentDirection) -> usize {
+        let mut extreme = 1;
+        let mut index = 3;
+        while index < self.points.len() {
+            if Decomposer::is_beyond(
+                direction,
+                self.points[index].price(),
+                self.points[extreme].price(),
+            ) {
+                extreme = index;
+            }
+            index += 2;
+        }
+        extreme
+    }
+
+    // 极值点之后反向走势的极值点
+    fn find_reverse_extreme(&self, direction: SegmentDirection, extreme: usize) -> Option<usize> {
+        let mut reverse: Option<usize> = None;
+        let mut index = extreme + 1;
+        while index < self.points.len() {
+            let is_reverse = match reverse {
+                Some(r) => Decomposer::is_beyond(
+                    direction.flip(),
+                    self.points[index].price(),
+                    self.points[r].price(),
+                ),
+                None => true,
+            };
+            if is_reverse {
+                reverse = Some(index);
+            }
+            index += 2;
+        }
+        reverse
+    }
+
+    fn make_move(&self, end: usize, direction: SegmentDirection) -> Move {
+        let mut detector = PivotDetector::new(PivotLevel::Segment);
+        let mut pivots: Vec<Pivot> = Vec::new();
+        let legs = (0..end).map(|i| Line::new_from_pen(&self.points[i], &self.points[i + 1]));
+        for line in self.enter.into_iter().chain(legs) {
+            for event in detector.on_new_line(line) {
+                match event {
+                    PivotEvent::New(pivot) => pivots.push(pivot),
+                    PivotEvent::Extend(pivot) | PivotEvent::Finish(pivot, _) => {
+                        *pivots.last_mut().unwrap() = pivot;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Move {
+            start: self.points[0].clone(),
+            end: self.points[end].clone(),
+            direction,
+            pivots,
+        }
+    }
+
+    fn process(&mut self) -> Vec<Move> {
+        let mut result = Vec::new();
+        while let Some(direction) = self.direction {
+            let extreme = self.find_extreme(direction);
+            let reverse = match self.find_reverse_extreme(direction, extreme) {
+                Some(reverse) => reverse,
+                None => break,
+            };
+            if reverse - extreme < 3 {
+                break;
+            }
+
+            // 反向走势已经有三个线段，当前走势结束
+            if extreme >= 3 || !self.moves.is_empty() {
+                let m = self.make_move(extreme, direction);
+                self.moves.push(m.clone());
+                result.push(m);
+            }
+            self.enter = Some(Line::new_from_pen(
+                &self.points[extreme - 1],
+                &self.points[extreme],
+            ));
+            self.points.drain(..extreme);
+            self.direction = Some(direction.flip());
+        }
+        result
+    }
+
+    fn add_point(&mut self, f: &Fractal) {
+        if self.points.is_empty() {
+            self.points.push(f.clone());
+            return;
+        }
+
+        if self.direction.is_none() {
+            let direction = if f.price() > self.points[0].price() {
+                SegmentDirection::Up
+            } else {
+                SegmentDirection::Down
+            };
+            self.direction = Some(direction);
+        }
+        self.points.push(f.clone());
+    }
+
+    fn on_new_segment(&mut self, from: &Fractal, to: &Fractal) {
+        let is_continuous = match self.points.last() {
+            Some(last) => last.time() == from.time(),
+            None => false,
+        };
+        if !is_continuous {
+            // 线段不连续(线段检测重新寻找第一个线段)，从新的线段重新开始分解
+            self.points.clear();
+            self.direction = None;
+            self.enter = None;
+            self.add_point(from);
+        }
+        self.add_point(to);
+    }
+
+    pub fn on_segment_event(&mut self, segment_event: &SegmentEvent) -> Vec<Move> {
+        match segment_event {
+            SegmentEvent::New(a, b) => {
+                self.on_new_segment(a, b);
+            }
+
+            SegmentEvent::New2(a, b, c) => {
+                self.on_new_segment(a, b);
+                self.on_new_segment(b, c);
+            }
+        }
+        self.process()
+    }
+}
+
+impl Default for Decomposer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::*;
+
+    fn feed(d: &mut Decomposer, prices: &[f64]) -> Vec<Move> {
+        let points = make_fractals(prices);
+        let mut moves = Vec::new();
+        for i in 1..points.len() {
+            let event = SegmentEvent::New(points[i - 1].clone(), points[i].clone());
+            moves.extend(d.on_segment_event(&event));
+        }
+        moves
+    }
+
+    #[test]
+    fn test_decompose() {
+        let mut d = Decomposer::new();
+        let moves = feed(&mut d, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0, 4.0, 5.5, 3.5]);
+        assert_eq!(moves.len(), 1);
+        let m = &moves[0];
+        assert_eq!(m.start.price(), 1.0);
+        assert_eq!(m.end.price(), 6.0);
+        assert_eq!(m.direction, SegmentDirection::Up);
+        assert_eq!(m.pivots.len(), 1);
+        assert_eq!(m.pivots[0].zg, 4.0);
+
+        let current = d.current().unwrap();
+        assert_eq!(current.direction, SegmentDirection::Down);
+        assert_eq!(current.start.price(), 6.0);
+        assert_eq!(current.end.price(), 3.5);
+    }
+
+    #[test]
+    fn test_drop_short_first_move() {
+        let mut d = Decomposer::new();
+        let moves = feed(&mut d, &[1.0, 10.0, 5.0, 8.0, 4.0, 7.0, 3.0]);
+        assert!(moves.is_empty());
+        let current = d.current().unwrap();
+        assert_eq!(current.direction, SegmentDirection::Down);
+        assert_eq!(current.start.price(), 10.0);
+        assert_eq!(current.end.price(), 3.0);
+    }
+}
@@ -0,0 +1,362 @@
+use crate::line::Line;
+use crate::macd::{Macd, MacdArea};
+use crate::pivot::{Pivot, PivotEvent, PivotLevel};
+use crate::time::Time;
+use crate::trend::{Trend, TrendType};
+
+// 趋势背驰
+// 趋势中最后一个中枢，比较离开中枢的一段(c)与进入中枢的一段(b)
+// c段创出新高(低)，但对应的MACD红(绿)柱面积或者峰值小于b段，即为背驰
+// 判断时机：离开中枢的一段确认时(PivotEvent::Leave)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Divergence {
+    pub level: PivotLevel,
+    pub pivot: Pivot,
+    // 进入中枢的一段
+    pub enter: Line,
+    pub enter_area: MacdArea,
+    // 离开中枢的一段
+    pub leave: Line,
+    pub leave_area: MacdArea,
+}
+
+impl Divergence {
+    pub fn is_up(&self) -> bool {
+        self.leave.is_up()
+    }
+
+    // 离开段力度与进入段力度的面积比
+    pub fn area_ratio(&self) -> f64 {
+        let (enter, _) = self.enter_area.strength(self.is_up());
+        let (leave, _) = self.leave_area.strength(self.is_up());
+        leave / enter
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DivergenceDetector {
+    level: PivotLevel,
+}
+
+impl DivergenceDetector {
+    pub fn new(level: PivotLevel) -> Self {
+        Self { level }
+    }
+
+    fn check(&self, pivot: &Pivot, leave: &Line, trend: &Trend, macd: &Macd) -> Option<Divergence> {
+        let enter = pivot.enter;
+        let up = enter.is_up();
+
+        // 离开段与进入段同向，且与趋势同向
+        let trend_type = if up { TrendType::Up } else { TrendType::Down };
+        if trend.trend_type != trend_type || leave.is_up() != up {
+            return None;
+        }
+
+        // 离开段创新高(低)
+        let is_new_extreme = if up {
+            leave.high() > pivot.gg
+        } else {
+            leave.low() < pivot.dd
+        };
+        if !is_new_extreme {
+            return None;
+        }
+
+        let enter_area = macd.line_area(&enter);
+        let leave_area = macd.line_area(leave);
+        let (enter_sum, enter_peak) = enter_area.strength(up);
+        let (leave_sum, leave_peak) = leave_area.strength(up);
+        if leave_sum < enter_sum || leave_peak < enter_peak {
+            Some(Divergence {
+                level: self.level,
+                pivot: pivot.clone(),
+                enter,
+                enter_area,
+                leave: *leave,
+                leave_area,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn on_pivot_event(
+        &mut self,
+        pivot_event: &PivotEvent,
+        trend: Option<&Trend>,
+        macd: &Macd,
+    ) -> Option<Divergence> {
+        match (pivot_event, trend) {
+            (PivotEvent::Leave(pivot, line), Some(trend)) => self.check(pivot, line, trend, macd),
+            _ => None,
+        }
+    }
+}
+
+// 盘整背驰
+// 只有一个中枢的盘整中，比较两段离开中枢的同向走势
+// 盘整背驰比较的两段，后一段与前一段K线数量比的上限
+const MAX_DURATION_RATIO: f64 = 2.0;
+
+// 后一段创出新高(低)，但MACD面积和价格斜率都小于前一段，即为盘整背驰
+// 第一次离开时和进入中枢的一段比较，之后和上一次同向离开的一段比较
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsolidationDivergence {
+    pub level: PivotLevel,
+    pub pivot: Pivot,
+    // 用于比较的前一段
+    pub prev: Line,
+    pub prev_area: MacdArea,
+    // 离开中枢的一段
+    pub leave: Line,
+    pub leave_area: MacdArea,
+    // 后一段与前一段的面积比
+    pub area_ratio: f64,
+    // 后一段与前一段的斜率比
+    pub slope_ratio: f64,
+    // 后一段与前一段的K线数量比
+    pub duration_ratio: f64,
+}
+
+impl ConsolidationDivergence {
+    pub fn is_up(&self) -> bool {
+        self.leave.is_up()
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsolidationDivergenceDetector {
+    level: PivotLevel,
+    // 当前中枢最近一次向上/向下离开的一段
+    last_up: Option<Line>,
+    last_down: Option<Line>,
+}
+
+impl ConsolidationDivergenceDetector {
+    pub fn new(level: PivotLevel) -> Self {
+        Self {
+            level,
+            last_up: None,
+            last_down: None,
+        }
+    }
+
+    // 用于比较的前一段的最早起点
+    pub fn earliest(&self) -> Option<Time> {
+        let up = self.last_up.map(|l| l.from().time());
+        let down = self.last_down.map(|l| l.from().time());
+        up.into_iter().chain(down).min()
+    }
+
+    fn reset(&mut self, pivot: &Pivot) {
+        self.last_up = None;
+        self.last_down = None;
+        if pivot.enter.is_up() {
+            self.last_up = Some(pivot.enter);
+        } else {
+            self.last_down = Some(pivot.enter);
+        }
+    }
+
+    fn check(
+        &mut self,
+        pivot: &Pivot,
+        leave: &Line,
+        macd: &Macd,
+    ) -> Option<ConsolidationDivergence> {
+        let up = leave.is_up();
+        let last = if up {
+            &mut self.last_up
+        } else {
+            &mut self.last_down
+        };
+        let prev = last.replace(*leave)?;
+
+        // 离开段创新高(低)
+        let is_new_extreme = if up {
+            leave.high() > prev.high()
+        } else {
+            leave.low() < prev.low()
+        };
+        if !is_new_extreme {
+            return None;
+        }
+
+        let prev_area = macd.line_area(&prev);
+        let leave_area = macd.line_area(leave);
+        let (prev_sum, _) = prev_area.strength(up);
+        let (leave_sum, _) = leave_area.strength(up);
+        // 前一段没有同向的MACD面积，无法比较力度
+        if prev_sum <= 0.0 || prev.slope() == 0.0 {
+            return None;
+        }
+        let area_ratio = leave_sum / prev_sum;
+        let slope_ratio = leave.slope() / prev.slope();
+        let duration_ratio = leave.bars() as f64 / prev.bars().max(1) as f64;
+        // 离开段的时间明显长于前一段时，面积和斜率不可比
+        if area_ratio < 1.0 && slope_ratio < 1.0 && duration_ratio <= MAX_DURATION_RATIO {
+            Some(ConsolidationDivergence {
+                level: self.level,
+                pivot: pivot.clone(),
+                prev,
+                prev_area,
+                leave: *leave,
+                leave_area,
+                area_ratio,
+                slope_ratio,
+                duration_ratio,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn on_pivot_event(
+        &mut self,
+        pivot_event: &PivotEvent,
+        trend: Option<&Trend>,
+        macd: &Macd,
+    ) -> Option<ConsolidationDivergence> {
+        match pivot_event {
+            PivotEvent::New(pivot) => {
+                self.reset(pivot);
+                None
+            }
+            PivotEvent::Leave(pivot, line) => match trend {
+                Some(trend) if trend.trend_type == TrendType::Consolidation => {
+                    self.check(pivot, line, macd)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bar::Bar;
+    use crate::pivot::PivotDetector;
+    use crate::test_util::tests::make_fractals;
+
+    #[test]
+    fn test_trend_divergence() {
+        // 价格路径：1 -> 4 (5根K) -> 2 -> 5 -> 3 (每段5根K) -> 5.5 (25根K，缓慢上涨)
+        let points = [
+            (0, 1.0),
+            (5, 4.0),
+            (10, 2.0),
+            (15, 5.0),
+            (20, 3.0),
+            (45, 5.5),
+        ];
+        let mut macd = Macd::standard();
+        for i in 1..points.len() {
+            let (t0, p0) = points[i - 1];
+            let (t1, p1) = points[i];
+            for t in t0..t1 {
+                let price = p0 + (p1 - p0) * (t - t0) as f64 / (t1 - t0) as f64;
+                macd.on_new_bar(&Bar::new(t, price, price, price, price));
+            }
+        }
+        macd.on_new_bar(&Bar::new(45, 5.5, 5.5, 5.5, 5.5));
+
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        let mut events = Vec::new();
+        for i in 1..points.len() {
+            let (t0, p0) = points[i - 1];
+            let (t1, p1) = points[i];
+            events.extend(pd.on_new_line(Line::new(t0, p0, t1, p1)));
+        }
+        let pivot = pd.pivot().unwrap().clone();
+        let trend = Trend {
+            level: PivotLevel::Pen,
+            trend_type: TrendType::Up,
+            pivots: vec![pivot],
+        };
+
+        let mut dd = DivergenceDetector::new(PivotLevel::Pen);
+        let divergence = events
+            .iter()
+            .filter_map(|e| dd.on_pivot_event(e, Some(&trend), &macd))
+            .next()
+            .unwrap();
+        assert!(divergence.is_up());
+        assert_eq!(divergence.leave.high(), 5.5);
+        assert_eq!(divergence.pivot.zg, 4.0);
+        assert_eq!(divergence.enter.high(), 4.0);
+
+        // 盘整中不做趋势背驰判断
+        let consolidation = Trend {
+            trend_type: TrendType::Consolidation,
+            ..trend.clone()
+        };
+        assert!(events
+            .iter()
+            .all(|e| dd.on_pivot_event(e, Some(&consolidation), &macd).is_none()));
+    }
+
+    #[test]
+    fn test_consolidation_divergence() {
+        // 价格路径：1 -> 4 -> 2 -> 3.5 -> 2.5 -> 4.5，每笔3根K
+        // 离开中枢的一笔(2.5 -> 4.5)创出新高，但斜率和面积都小于进入中枢的一笔(1 -> 4)
+        let prices = [1.0, 4.0, 2.0, 3.5, 2.5, 4.5];
+        let fractals = make_fractals(&prices);
+        let mut macd = Macd::standard();
+        for i in 1..fractals.len() {
+            let (t0, p0) = (fractals[i - 1].time(), prices[i - 1]);
+            let (t1, p1) = (fractals[i].time(), prices[i]);
+            for t in t0..t1 {
+                let price = p0 + (p1 - p0) * (t - t0) as f64 / (t1 - t0) as f64;
+                macd.on_new_bar(&Bar::new(t, price, price, price, price));
+            }
+        }
+
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        let mut events = Vec::new();
+        for i in 1..fractals.len() {
+            events.extend(pd.on_new_line(Line::new_from_pen(&fractals[i - 1], &fractals[i])));
+        }
+        let trend = Trend {
+            level: PivotLevel::Pen,
+            trend_type: TrendType::Consolidation,
+            pivots: vec![pd.pivot().unwrap().clone()],
+        };
+
+        let mut cd = ConsolidationDivergenceDetector::new(PivotLevel::Pen);
+        let divergence = events
+            .iter()
+            .filter_map(|e| cd.on_pivot_event(e, Some(&trend), &macd))
+            .next()
+            .unwrap();
+        assert!(divergence.is_up());
+        assert_eq!(divergence.prev.high(), 4.0);
+        assert_eq!(divergence.leave.high(), 4.5);
+        assert!(divergence.area_ratio < 1.0);
+        assert!(divergence.slope_ratio < 1.0);
+        assert_eq!(divergence.duration_ratio, 1.0);
+
+        // 趋势中不做盘整背驰判断
+        let up = Trend {
+            trend_type: TrendType::Up,
+            ..trend.clone()
+        };
+        let mut cd = ConsolidationDivergenceDetector::new(PivotLevel::Pen);
+        assert!(events
+            .iter()
+            .all(|e| cd.on_pivot_event(e, Some(&up), &macd).is_none()));
+
+        // 没有MACD面积时不做比较
+        let empty = Macd::standard();
+        let mut cd = ConsolidationDivergenceDetector::new(PivotLevel::Pen);
+        assert!(events
+            .iter()
+            .all(|e| cd.on_pivot_event(e, Some(&trend), &empty).is_none()));
+    }
+}
@@ -1,28 +1,115 @@
+use std::fmt;
+
+pub type SubscriberId = usize;
+
+type Filter<'a, T> = Box<dyn Fn(&T) -> bool + 'a>;
+
+struct Subscriber<'a, T> {
+    id: SubscriberId,
+    // 过滤条件，None表示接收所有事件
+    filter: Option<Filter<'a, T>>,
+    observer: Box<dyn FnMut(&T) + 'a>,
+}
+
+// 事件分发，支持多个订阅者，按订阅的先后顺序调用
 pub struct Emitter<'a, T> {
-    observer: Option<Box<dyn FnMut(&T) + 'a>>,
+    subscribers: Vec<Subscriber<'a, T>>,
+    next_id: SubscriberId,
 }
 
 impl<'a, T> Emitter<'a, T> {
     pub fn new() -> Self {
-        Self { observer: None }
+        Self {
+            subscribers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn subscribe<F>(&mut self, observer: F) -> SubscriberId
+    where
+        F: 'a + FnMut(&T),
+    {
+        self.add(None, Box::new(observer))
     }
 
-    pub fn set_observer<F>(&mut self, observer: F)
-    //-> Option<Box<dyn Fn(&T)>>
+    // 只接收filter返回true的事件
+    pub fn subscribe_filter<P, F>(&mut self, filter: P, observer: F) -> SubscriberId
     where
+        P: 'a + Fn(&T) -> bool,
         F: 'a + FnMut(&T),
     {
-        //let result = self.observers;
-        self.observer = Some(Box::new(observer));
-        //result
+        self.add(Some(Box::new(filter)), Box::new(observer))
+    }
+
+    fn add(
+        &mut self,
+        filter: Option<Filter<'a, T>>,
+        observer: Box<dyn FnMut(&T) + 'a>,
+    ) -> SubscriberId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.push(Subscriber {
+            id,
+            filter,
+            observer,
+        });
+        id
+    }
+
+    // 返回是否存在该订阅者
+    pub fn unsubscribe(&mut self, id: SubscriberId) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|s| s.id != id);
+        self.subscribers.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
     }
 
     pub fn emit(&mut self, item: &T) {
-        if self.observer.is_none() {
-            return;
+        for subscriber in self.subscribers.iter_mut() {
+            if subscriber.filter.as_ref().is_none_or(|f| f(item)) {
+                (subscriber.observer)(item);
+            }
         }
+    }
+}
 
-        let f = self.observer.as_mut().unwrap();
-        (*f)(item)
+impl<'a, T> Default for Emitter<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> fmt::Debug for Emitter<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Emitter")
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_emitter() {
+        let all = RefCell::new(Vec::new());
+        let even = RefCell::new(Vec::new());
+        let mut emitter = Emitter::new();
+        let id = emitter.subscribe(|x: &i32| all.borrow_mut().push(*x));
+        emitter.subscribe_filter(|x: &i32| x % 2 == 0, |x: &i32| even.borrow_mut().push(*x));
+        for i in 0..4 {
+            emitter.emit(&i);
+        }
+        assert!(emitter.unsubscribe(id));
+        assert!(!emitter.unsubscribe(id));
+        emitter.emit(&4);
+        drop(emitter);
+        assert_eq!(all.into_inner(), vec![0, 1, 2, 3]);
+        assert_eq!(even.into_inner(), vec![0, 2, 4]);
     }
 }
@@ -0,0 +1,497 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::analyzer::{Analyzer, AnalyzerEvent, EventKind};
+use crate::bar::Bar;
+use crate::buffer::{BufferIndicator, Buffers, Rates};
+use crate::lifecycle::{LifecycleEvent, Object, ObjectKind};
+use crate::line::Line;
+use crate::pivot::{Pivot, PivotLevel};
+use crate::signals::{Side, Signal};
+
+// C接口，头文件include/rzen.h由cbindgen生成：cbindgen --config cbindgen.toml -o include/rzen.h
+// 每根K线之后用rzen_analyzer_poll_events取出事件，未取出的事件一直保留
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RzenKind {
+    // 分型检测得到的分型
+    Fractal = 0,
+    Pen = 1,
+    Segment = 2,
+    Pivot = 3,
+    Signal = 4,
+    // 笔的端点
+    Point = 5,
+    // 包含处理之后的K线
+    Candle = 6,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RzenAction {
+    New = 0,
+    Update = 1,
+    Confirm = 2,
+    // 撤销之前的对象，第三类买卖点取消也用Retract表示
+    Retract = 3,
+}
+
+// K线: from为(时间, 最低价)，to为(时间, 最高价)
+// 分型、笔的端点: from和to都是分型的时间和价格
+// 笔、线段: from为起点，to为终点
+// 中枢: from为(开始时间, ZD)，to为(结束时间, ZG)
+// 买卖点: from为买卖点的时间和价格，to_time为发出信号的K线时间
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RzenEvent {
+    pub kind: RzenKind,
+    pub action: RzenAction,
+    // 分型、笔、线段、中枢的ID，Confirm/Retract通过ID引用之前的New，买卖点为0
+    pub id: u64,
+    // 中枢和买卖点的级别，0为笔，1为线段，更高的级别依次增加
+    pub level: u32,
+    // 买卖点的类型：1、2、3，其他事件为0
+    pub signal: u32,
+    // 1为买，-1为卖，其他事件为0
+    pub side: i32,
+    pub from_time: i64,
+    pub from_price: f64,
+    pub to_time: i64,
+    pub to_price: f64,
+}
+
+impl RzenEvent {
+    fn new(kind: RzenKind, action: RzenAction, id: u64) -> Self {
+        Self {
+            kind,
+            action,
+            id,
+            level: 0,
+            signal: 0,
+            side: 0,
+            from_time: 0,
+            from_price: 0.0,
+            to_time: 0,
+            to_price: 0.0,
+        }
+    }
+
+    fn with_line(mut self, line: &Line) -> Self {
+        self.from_time = line.from().time();
+        self.from_price = line.from().price();
+        self.to_time = line.to().time();
+        self.to_price = line.to().price();
+        self
+    }
+
+    fn with_pivot(mut self, pivot: &Pivot) -> Self {
+        self.level = pivot.level.as_index();
+        self.from_time = pivot.start;
+        self.from_price = pivot.zd;
+        self.to_time = pivot.end;
+        self.to_price = pivot.zg;
+        self
+    }
+}
+
+fn kind(kind: ObjectKind) -> RzenKind {
+    match kind {
+        ObjectKind::Candle => RzenKind::Candle,
+        ObjectKind::Fractal => RzenKind::Fractal,
+        ObjectKind::Point => RzenKind::Point,
+        ObjectKind::Pen => RzenKind::Pen,
+        ObjectKind::Segment => RzenKind::Segment,
+        ObjectKind::Pivot => RzenKind::Pivot,
+    }
+}
+
+fn object_event(action: RzenAction, id: u64, object: &Object) -> RzenEvent {
+    let event = RzenEvent::new(kind(object.kind()), action, id);
+    match object {
+        Object::Candle(bar) => {
+            let line = Line::new(bar.time, bar.low, bar.time, bar.high);
+            event.with_line(&line)
+        }
+        Object::Fractal(f) | Object::Point(f) => {
+            let line = Line::new(f.time(), f.price(), f.time(), f.price());
+            event.with_line(&line)
+        }
+        Object::Pen(line) | Object::Segment(line) => event.with_line(line),
+        Object::Pivot(pivot) => event.with_pivot(pivot),
+    }
+}
+
+fn signal_event(signal: &Signal) -> RzenEvent {
+    let (action, number, emitted) = match signal {
+        Signal::First(p) => (RzenAction::New, 1, p.time),
+        Signal::Second(p) => (RzenAction::New, 2, p.time),
+        Signal::Third(p) => (RzenAction::New, 3, p.time),
+        Signal::ThirdCancel(_, time) => (RzenAction::Retract, 3, *time),
+    };
+    let (pivot_level, side, fractal) = match signal {
+        Signal::First(p) => (p.level, p.side, &p.fractal),
+        Signal::Second(p) => (p.level, p.side, &p.fractal),
+        Signal::Third(p) | Signal::ThirdCancel(p, _) => (p.level, p.side, &p.fractal),
+    };
+    let mut event = RzenEvent::new(RzenKind::Signal, action, 0);
+    event.signal = number;
+    event.level = pivot_level.as_index();
+    event.side = match side {
+        Side::Buy => 1,
+        Side::Sell => -1,
+    };
+    event.from_time = fractal.time();
+    event.from_price = fractal.price();
+    event.to_time = emitted;
+    event
+}
+
+fn convert(event: &AnalyzerEvent) -> Option<RzenEvent> {
+    match event {
+        AnalyzerEvent::Lifecycle(event) => Some(match event {
+            LifecycleEvent::New(id, object) => object_event(RzenAction::New, *id, object),
+            LifecycleEvent::Update(id, object) => object_event(RzenAction::Update, *id, object),
+            LifecycleEvent::Confirm(k, id) => RzenEvent::new(kind(*k), RzenAction::Confirm, *id),
+            LifecycleEvent::Retract(k, id) => RzenEvent::new(kind(*k), RzenAction::Retract, *id),
+        }),
+        AnalyzerEvent::Signal(signal) => Some(signal_event(signal)),
+        _ => None,
+    }
+}
+
+// C接口使用的不透明句柄
+pub struct RzenAnalyzer {
+    analyzer: Analyzer,
+    events: Rc<RefCell<VecDeque<RzenEvent>>>,
+}
+
+impl RzenAnalyzer {
+    fn new() -> Self {
+        let mut analyzer = Analyzer::new();
+        let events = Rc::new(RefCell::new(VecDeque::new()));
+        let queue = events.clone();
+        analyzer.subscribe_kinds(&[EventKind::Lifecycle, EventKind::Signal], move |e| {
+            queue.borrow_mut().extend(convert(e));
+        });
+        Self { analyzer, events }
+    }
+}
+
+// panic不能跨越C接口，发生panic时返回default，之后句柄的状态不确定，应该释放
+fn guard<T, F>(default: T, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(default)
+}
+
+/// 创建Analyzer，用rzen_analyzer_free释放，失败时返回NULL
+#[no_mangle]
+pub extern "C" fn rzen_analyzer_new() -> *mut RzenAnalyzer {
+    guard(ptr::null_mut(), || {
+        Box::into_raw(Box::new(RzenAnalyzer::new()))
+    })
+}
+
+/// # Safety
+/// analyzer必须是rzen_analyzer_new返回的指针或者NULL，释放之后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn rzen_analyzer_free(analyzer: *mut RzenAnalyzer) {
+    if !analyzer.is_null() {
+        guard((), || drop(Box::from_raw(analyzer)));
+    }
+}
+
+/// 输入一根完成的K线，time为毫秒，返回尚未取出的事件数量
+/// analyzer为NULL或者处理失败时不做任何处理，返回0
+///
+/// # Safety
+/// analyzer必须是rzen_analyzer_new返回的有效指针或者NULL
+#[no_mangle]
+pub unsafe extern "C" fn rzen_analyzer_push_bar(
+    analyzer: *mut RzenAnalyzer,
+    time: i64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+) -> usize {
+    if analyzer.is_null() {
+        return 0;
+    }
+    guard(0, || {
+        let analyzer = &mut *analyzer;
+        analyzer
+            .analyzer
+            .on_new_bar(&Bar::new(time, open, high, low, close));
+        analyzer.events.borrow().len()
+    })
+}
+
+/// 尚未取出的事件数量，analyzer为NULL时返回0
+///
+/// # Safety
+/// analyzer必须是rzen_analyzer_new返回的有效指针或者NULL
+#[no_mangle]
+pub unsafe extern "C" fn rzen_analyzer_pending_events(analyzer: *const RzenAnalyzer) -> usize {
+    if analyzer.is_null() {
+        return 0;
+    }
+    guard(0, || (*analyzer).events.borrow().len())
+}
+
+/// 按发生的顺序取出最多capacity个事件写入events，返回写入的数量
+/// analyzer或events为NULL时返回0
+///
+/// # Safety
+/// analyzer必须是rzen_analyzer_new返回的有效指针或者NULL，events至少有capacity个元素
+#[no_mangle]
+pub unsafe extern "C" fn rzen_analyzer_poll_events(
+    analyzer: *mut RzenAnalyzer,
+    events: *mut RzenEvent,
+    capacity: usize,
+) -> usize {
+    if analyzer.is_null() || events.is_null() {
+        return 0;
+    }
+    guard(0, || {
+        let mut queue = (*analyzer).events.borrow_mut();
+        let count = capacity.min(queue.len());
+        for (i, event) in queue.drain(..count).enumerate() {
+            events.add(i).write(event);
+        }
+        count
+    })
+}
+
+// MetaTrader指标的输出缓冲区，每个指针指向rates_total个double
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RzenBuffers {
+    pub fractal_top: *mut f64,
+    pub fractal_bottom: *mut f64,
+    pub pen: *mut f64,
+    pub segment: *mut f64,
+    pub pivot_high: *mut f64,
+    pub pivot_low: *mut f64,
+}
+
+impl RzenBuffers {
+    fn is_null(&self) -> bool {
+        self.fractal_top.is_null()
+            || self.fractal_bottom.is_null()
+            || self.pen.is_null()
+            || self.segment.is_null()
+            || self.pivot_high.is_null()
+            || self.pivot_low.is_null()
+    }
+}
+
+/// 创建指标缓冲区的计算器，level为显示的中枢级别：0为笔中枢，1为线段中枢
+/// 用rzen_buffers_free释放，level不支持或者失败时返回NULL
+#[no_mangle]
+pub extern "C" fn rzen_buffers_new(level: u32) -> *mut BufferIndicator {
+    let level = match level {
+        0 => PivotLevel::Pen,
+        1 => PivotLevel::Segment,
+        n => PivotLevel::Higher(n - 1),
+    };
+    guard(ptr::null_mut(), || match BufferIndicator::new(level) {
+        Some(indicator) => Box::into_raw(Box::new(indicator)),
+        None => ptr::null_mut(),
+    })
+}
+
+/// # Safety
+/// indicator必须是rzen_buffers_new返回的指针或者NULL，释放之后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn rzen_buffers_free(indicator: *mut BufferIndicator) {
+    if !indicator.is_null() {
+        guard((), || drop(Box::from_raw(indicator)));
+    }
+}
+
+/// 在OnCalculate中调用，参数与OnCalculate相同，time为毫秒，数组按时间从旧到新排列(ArraySetAsSeries为false)
+/// 返回值作为OnCalculate的返回值，first_changed为输出发生变化的起始下标，可以为NULL
+/// indicator、数组或缓冲区为NULL以及计算失败时不做任何处理，返回0，下一次重新计算
+///
+/// # Safety
+/// indicator必须是rzen_buffers_new返回的有效指针或者NULL，所有数组至少有rates_total个元素
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn rzen_buffers_calculate(
+    indicator: *mut BufferIndicator,
+    rates_total: usize,
+    prev_calculated: usize,
+    time: *const i64,
+    open: *const f64,
+    high: *const f64,
+    low: *const f64,
+    close: *const f64,
+    buffers: *const RzenBuffers,
+    first_changed: *mut usize,
+) -> usize {
+    use std::slice::{from_raw_parts, from_raw_parts_mut};
+    if rates_total == 0 || indicator.is_null() || buffers.is_null() || (*buffers).is_null() {
+        return 0;
+    }
+    if time.is_null() || open.is_null() || high.is_null() || low.is_null() || close.is_null() {
+        return 0;
+    }
+    guard(0, || {
+        let rates = Rates {
+            time: from_raw_parts(time, rates_total),
+            open: from_raw_parts(open, rates_total),
+            high: from_raw_parts(high, rates_total),
+            low: from_raw_parts(low, rates_total),
+            close: from_raw_parts(close, rates_total),
+        };
+        let b = &*buffers;
+        let mut buffers = Buffers {
+            fractal_top: from_raw_parts_mut(b.fractal_top, rates_total),
+            fractal_bottom: from_raw_parts_mut(b.fractal_bottom, rates_total),
+            pen: from_raw_parts_mut(b.pen, rates_total),
+            segment: from_raw_parts_mut(b.segment, rates_total),
+            pivot_high: from_raw_parts_mut(b.pivot_high, rates_total),
+            pivot_low: from_raw_parts_mut(b.pivot_low, rates_total),
+        };
+        let (calculated, from) = (*indicator).calculate(&rates, prev_calculated, &mut buffers);
+        if !first_changed.is_null() {
+            *first_changed = from;
+        }
+        calculated
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::EMPTY_VALUE;
+    use crate::test_util::tests::*;
+
+    #[test]
+    fn test_ffi() {
+        let bars = load_eurusd_2021();
+        unsafe {
+            let analyzer = rzen_analyzer_new();
+            let mut events = Vec::new();
+            let mut buffer = [RzenEvent::new(RzenKind::Pen, RzenAction::New, 0); 4];
+            for bar in &bars {
+                rzen_analyzer_push_bar(analyzer, bar.time, bar.open, bar.high, bar.low, bar.close);
+                loop {
+                    let count =
+                        rzen_analyzer_poll_events(analyzer, buffer.as_mut_ptr(), buffer.len());
+                    events.extend_from_slice(&buffer[..count]);
+                    if count < buffer.len() {
+                        break;
+                    }
+                }
+            }
+            assert_eq!(rzen_analyzer_pending_events(analyzer), 0);
+
+            let is_pen = |e: &&RzenEvent| e.kind == RzenKind::Pen;
+            let new = events
+                .iter()
+                .filter(is_pen)
+                .filter(|e| e.action == RzenAction::New);
+            assert_eq!(new.count(), (*analyzer).analyzer.pens().len());
+            let signals = events.iter().filter(|e| e.kind == RzenKind::Signal);
+            assert_eq!(signals.count(), (*analyzer).analyzer.signals().len());
+            rzen_analyzer_free(analyzer);
+        }
+    }
+
+    #[test]
+    fn test_ffi_buffers() {
+        let bars = &load_eurusd_2021()[..2000];
+        let time: Vec<i64> = bars.iter().map(|b| b.time).collect();
+        let column = |f: fn(&Bar) -> f64| bars.iter().map(f).collect::<Vec<f64>>();
+        let (open, high, low, close) = (
+            column(|b| b.open),
+            column(|b| b.high),
+            column(|b| b.low),
+            column(|b| b.close),
+        );
+        let mut values = vec![vec![0.0; bars.len()]; 6];
+        let pointers: Vec<*mut f64> = values.iter_mut().map(|v| v.as_mut_ptr()).collect();
+        let buffers = RzenBuffers {
+            fractal_top: pointers[0],
+            fractal_bottom: pointers[1],
+            pen: pointers[2],
+            segment: pointers[3],
+            pivot_high: pointers[4],
+            pivot_low: pointers[5],
+        };
+        unsafe {
+            let indicator = rzen_buffers_new(0);
+            let mut from = usize::MAX;
+            let calculated = rzen_buffers_calculate(
+                indicator,
+                bars.len(),
+                0,
+                time.as_ptr(),
+                open.as_ptr(),
+                high.as_ptr(),
+                low.as_ptr(),
+                close.as_ptr(),
+                &buffers,
+                &mut from,
+            );
+            assert_eq!(calculated, bars.len());
+            assert_eq!(from, 0);
+            let pens = (*indicator).analyzer().pens().len();
+            rzen_buffers_free(indicator);
+            let points = values[2].iter().filter(|v| **v != EMPTY_VALUE);
+            assert_eq!(points.count(), pens + 1);
+        }
+    }
+
+    #[test]
+    fn test_ffi_null() {
+        unsafe {
+            let analyzer = ptr::null_mut();
+            assert_eq!(rzen_analyzer_push_bar(analyzer, 0, 1.0, 1.0, 1.0, 1.0), 0);
+            assert_eq!(rzen_analyzer_pending_events(analyzer), 0);
+            let mut events = [RzenEvent::new(RzenKind::Pen, RzenAction::New, 0); 4];
+            assert_eq!(
+                rzen_analyzer_poll_events(analyzer, events.as_mut_ptr(), 4),
+                0
+            );
+            rzen_analyzer_free(analyzer);
+
+            // 缓冲区为NULL时不做任何处理
+            let indicator = rzen_buffers_new(0);
+            let rates = [1.0];
+            let mut value = [0.0];
+            let buffers = RzenBuffers {
+                fractal_top: value.as_mut_ptr(),
+                fractal_bottom: value.as_mut_ptr(),
+                pen: value.as_mut_ptr(),
+                segment: value.as_mut_ptr(),
+                pivot_high: value.as_mut_ptr(),
+                pivot_low: ptr::null_mut(),
+            };
+            let calculated = rzen_buffers_calculate(
+                indicator,
+                1,
+                0,
+                [0].as_ptr(),
+                rates.as_ptr(),
+                rates.as_ptr(),
+                rates.as_ptr(),
+                rates.as_ptr(),
+                &buffers,
+                ptr::null_mut(),
+            );
+            assert_eq!(calculated, 0);
+            assert!((*indicator).analyzer().candles().is_empty());
+            rzen_buffers_free(indicator);
+            rzen_buffers_free(ptr::null_mut());
+            assert!(rzen_buffers_new(2).is_null());
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use crate::{candle::Candle, time::Time};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FractalType {
     Top,
     Bottom,
 }
 // 分型
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Fractal {
     pub(crate) k1: Candle,
     pub(crate) k2: Candle,
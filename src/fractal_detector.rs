@@ -3,6 +3,8 @@ use crate::candle::Candle;
 use crate::fractal::Fractal;
 use crate::ringbuffer::RingBuffer;
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FractalDetector {
     window: RingBuffer<Candle>,
     next_index: u64,
@@ -10,6 +12,7 @@ pub struct FractalDetector {
 }
 
 impl FractalDetector {
+    #[allow(dead_code)]
     pub fn new() -> Self {
         Self {
             window: RingBuffer::new(3),
@@ -18,7 +21,6 @@ impl FractalDetector {
         }
     }
 
-    #[allow(dead_code)]
     pub fn with_candles() -> Self {
         Self {
             window: RingBuffer::new(3),
@@ -32,6 +34,28 @@ impl FractalDetector {
         self.candles.as_ref()
     }
 
+    // 正在形成的K线，之后的K线可能与它合并
+    pub fn last_candle(&self) -> Option<&Candle> {
+        self.window.get(-1)
+    }
+
+    // 取出已经记录的K线，之后只记录新完成的K线
+    pub(crate) fn take_candles(&mut self) -> Vec<Bar> {
+        self.candles
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // 用于临时计算的副本，不复制已经记录的K线
+    pub(crate) fn snapshot(&self) -> Self {
+        Self {
+            window: self.window.clone(),
+            next_index: self.next_index,
+            candles: None,
+        }
+    }
+
     fn notify(&mut self) {
         if let Some(container) = self.candles.as_mut() {
             if self.window.len() > 0 {
@@ -1,16 +1,35 @@
 pub mod analyzer;
 pub mod bar;
+pub mod buffer;
 mod candle;
+pub mod decompose;
+pub mod divergence;
+pub mod emitter;
+pub mod ffi;
 pub mod fractal;
 mod fractal_detector;
-//pub mod emitter;
+pub mod lifecycle;
 pub mod line;
+pub mod macd;
+pub mod multi_level;
 pub mod pen_detector;
 pub mod pivot;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod plot;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod recursive;
+pub mod resample;
 mod ringbuffer;
 pub mod sd2;
+pub mod signals;
+#[cfg(feature = "serde")]
+pub mod snapshot;
 //pub mod segment_detector;
 //pub mod sequence;
 pub(crate) mod test_util;
+pub mod tick;
 pub mod time;
+pub mod trend;
+#[cfg(feature = "wasm")]
+pub mod wasm;
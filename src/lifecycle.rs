@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::bar::Bar;
+use crate::fractal::Fractal;
+use crate::line::Line;
+use crate::pen_detector::PenEvent;
+use crate::pivot::{Pivot, PivotEvent, PivotLevel};
+use crate::sd2::SegmentEvent;
+use crate::time::Time;
+
+// K线、分型、笔、线段、中枢的生命周期
+// 每个对象出现时分配一个不变的ID，之后的变化、确认和撤销都用这个ID表示
+// UI和交易系统可以据此删除或锁定之前的绘图和信号，而不需要比较前后状态
+// 包含处理之后的K线在下一根K线出现时才完成，完成即确认
+// 分型检测只在第三根K线完成之后输出分型，分型出现即确认，不会被撤销
+// 中枢只由已经确认的笔(线段)构成，不会被撤销，扩张形成的高级别中枢在继续扩张时更新
+pub type ObjectId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ObjectKind {
+    // 包含处理之后的K线
+    Candle,
+    // 分型检测得到的分型
+    Fractal,
+    // 笔的端点
+    Point,
+    Pen,
+    Segment,
+    Pivot,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Object {
+    Candle(Bar),
+    Fractal(Fractal),
+    Point(Fractal),
+    Pen(Line),
+    Segment(Line),
+    Pivot(Pivot),
+}
+
+impl Object {
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            Object::Candle(_) => ObjectKind::Candle,
+            Object::Fractal(_) => ObjectKind::Fractal,
+            Object::Point(_) => ObjectKind::Point,
+            Object::Pen(_) => ObjectKind::Pen,
+            Object::Segment(_) => ObjectKind::Segment,
+            Object::Pivot(_) => ObjectKind::Pivot,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LifecycleEvent {
+    // 新对象，尚未确认
+    New(ObjectId, Object),
+    // 尚未确认的对象发生变化，例如笔的终点延伸、中枢延伸
+    Update(ObjectId, Object),
+    // 对象已经确认，之后不会再变化
+    Confirm(ObjectKind, ObjectId),
+    // 之前发出的对象不再有效
+    Retract(ObjectKind, ObjectId),
+}
+
+// 带有ID的对象，confirmed为false表示仍可能变化或被撤销
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tracked<T> {
+    pub id: ObjectId,
+    pub value: T,
+    pub confirmed: bool,
+}
+
+impl<T> Tracked<T> {
+    pub fn new(id: ObjectId, value: T) -> Self {
+        Self {
+            id,
+            value,
+            confirmed: false,
+        }
+    }
+}
+
+// 把各个检测器的输出转换成生命周期事件
+// K线和分型出现时即确认
+// 笔的终点被UpdateTo替换时撤销原终点，笔在下一笔出现时确认
+// 线段在SegmentEvent发出时确认，正在延伸的线段重新开始寻找时撤销
+// 中枢结束时确认；扩张形成的高级别中枢在中枢新生或者重新开始寻找中枢时确认
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LifecycleTracker {
+    next_id: ObjectId,
+    // 当前笔的起点
+    from: Option<Fractal>,
+    // 当前笔的终点，尚未确认
+    to: Option<(ObjectId, Fractal)>,
+    pen: Option<ObjectId>,
+    // 正在延伸的线段，(ID, 起点时间, 终点时间)
+    segment: Option<(ObjectId, Time, Time)>,
+    // 各级别正在延伸的中枢
+    pivots: BTreeMap<PivotLevel, ObjectId>,
+    // 扩张形成的高级别中枢，按参与扩张的中枢级别索引
+    expanded: BTreeMap<PivotLevel, ObjectId>,
+    // 新中枢已经出现，等待与前中枢的扩张或新生关系
+    relating: BTreeSet<PivotLevel>,
+}
+
+impl LifecycleTracker {
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            from: None,
+            to: None,
+            pen: None,
+            segment: None,
+            pivots: BTreeMap::new(),
+            expanded: BTreeMap::new(),
+            relating: BTreeSet::new(),
+        }
+    }
+
+    fn next_id(&mut self) -> ObjectId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn on_candle(&mut self, candle: &Bar) -> Vec<LifecycleEvent> {
+        let id = self.next_id();
+        vec![
+            LifecycleEvent::New(id, Object::Candle(candle.clone())),
+            LifecycleEvent::Confirm(ObjectKind::Candle, id),
+        ]
+    }
+
+    pub fn on_fractal(&mut self, fractal: &Fractal) -> Vec<LifecycleEvent> {
+        let id = self.next_id();
+        vec![
+            LifecycleEvent::New(id, Object::Fractal(fractal.clone())),
+            LifecycleEvent::Confirm(ObjectKind::Fractal, id),
+        ]
+    }
+
+    // 新的笔终点
+    fn new_point(&mut self, point: &Fractal, events: &mut Vec<LifecycleEvent>) {
+        let id = self.next_id();
+        events.push(LifecycleEvent::New(id, Object::Point(point.clone())));
+        self.to = Some((id, point.clone()));
+    }
+
+    pub fn on_pen_event(&mut self, pen_event: &PenEvent) -> Vec<LifecycleEvent> {
+        let mut events = Vec::new();
+        match pen_event {
+            PenEvent::First(a, b) => {
+                let id = self.next_id();
+                events.push(LifecycleEvent::New(id, Object::Point(a.clone())));
+                events.push(LifecycleEvent::Confirm(ObjectKind::Point, id));
+                self.from = Some(a.clone());
+                self.new_point(b, &mut events);
+                let id = self.next_id();
+                events.push(LifecycleEvent::New(
+                    id,
+                    Object::Pen(Line::new_from_pen(a, b)),
+                ));
+                self.pen = Some(id);
+            }
+
+            PenEvent::New(c) => {
+                // 新笔出现，前一笔及其终点确认
+                let (point_id, point) = self.to.take().unwrap();
+                events.push(LifecycleEvent::Confirm(ObjectKind::Point, point_id));
+                events.push(LifecycleEvent::Confirm(ObjectKind::Pen, self.pen.unwrap()));
+                self.new_point(c, &mut events);
+                let id = self.next_id();
+                let line = Line::new_from_pen(&point, c);
+                events.push(LifecycleEvent::New(id, Object::Pen(line)));
+                self.from = Some(point);
+                self.pen = Some(id);
+            }
+
+            PenEvent::UpdateTo(c) => {
+                // 笔延伸，原来的终点撤销
+                let (point_id, _) = self.to.take().unwrap();
+                events.push(LifecycleEvent::Retract(ObjectKind::Point, point_id));
+                self.new_point(c, &mut events);
+                let line = Line::new_from_pen(self.from.as_ref().unwrap(), c);
+                events.push(LifecycleEvent::Update(self.pen.unwrap(), Object::Pen(line)));
+            }
+        }
+        events
+    }
+
+    fn confirm_segment(&mut self, a: &Fractal, b: &Fractal, events: &mut Vec<LifecycleEvent>) {
+        let line = Line::new_from_pen(a, b);
+        let id = match self.segment.take() {
+            Some((id, start, end)) if start == a.time() => {
+                if end != b.time() {
+                    events.push(LifecycleEvent::Update(id, Object::Segment(line)));
+                }
+                id
+            }
+            other => {
+                if let Some((id, _, _)) = other {
+                    events.push(LifecycleEvent::Retract(ObjectKind::Segment, id));
+                }
+                let id = self.next_id();
+                events.push(LifecycleEvent::New(id, Object::Segment(line)));
+                id
+            }
+        };
+        events.push(LifecycleEvent::Confirm(ObjectKind::Segment, id));
+    }
+
+    pub fn on_segment_event(&mut self, segment_event: &SegmentEvent) -> Vec<LifecycleEvent> {
+        let mut events = Vec::new();
+        match segment_event {
+            SegmentEvent::New(a, b) => self.confirm_segment(a, b, &mut events),
+            SegmentEvent::New2(a, b, c) => {
+                self.confirm_segment(a, b, &mut events);
+                self.confirm_segment(b, c, &mut events);
+            }
+        }
+        events
+    }
+
+    // 每次线段检测之后，用SegmentDetector::current_segment更新正在延伸的线段
+    pub fn on_segment_state(
+        &mut self,
+        current: Option<(&Fractal, &Fractal)>,
+    ) -> Vec<LifecycleEvent> {
+        let mut events = Vec::new();
+        let current = current.filter(|(a, b)| a.time() != b.time());
+        match (self.segment, current) {
+            (Some((id, start, end)), Some((a, b))) if start == a.time() => {
+                if end != b.time() {
+                    let line = Line::new_from_pen(a, b);
+                    events.push(LifecycleEvent::Update(id, Object::Segment(line)));
+                    self.segment = Some((id, start, b.time()));
+                }
+            }
+            (prev, Some((a, b))) => {
+                if let Some((id, _, _)) = prev {
+                    events.push(LifecycleEvent::Retract(ObjectKind::Segment, id));
+                }
+                let id = self.next_id();
+                let line = Line::new_from_pen(a, b);
+                events.push(LifecycleEvent::New(id, Object::Segment(line)));
+                self.segment = Some((id, a.time(), b.time()));
+            }
+            (Some((id, _, _)), None) => {
+                events.push(LifecycleEvent::Retract(ObjectKind::Segment, id));
+                self.segment = None;
+            }
+            (None, None) => {}
+        }
+        events
+    }
+
+    fn confirm_expanded(&mut self, level: PivotLevel, events: &mut Vec<LifecycleEvent>) {
+        if let Some(id) = self.expanded.remove(&level) {
+            events.push(LifecycleEvent::Confirm(ObjectKind::Pivot, id));
+        }
+    }
+
+    pub fn on_pivot_event(&mut self, pivot_event: &PivotEvent) -> Vec<LifecycleEvent> {
+        let mut events = Vec::new();
+        let level = match pivot_event {
+            PivotEvent::New(pivot)
+            | PivotEvent::Extend(pivot)
+            | PivotEvent::Leave(pivot, _)
+            | PivotEvent::Finish(pivot, _)
+            | PivotEvent::Expand(_, pivot, _)
+            | PivotEvent::Reborn(_, pivot) => pivot.level,
+        };
+        // 新中枢之后没有扩张或新生，说明中枢检测重新开始，之前扩张形成的中枢结束
+        let is_relation = matches!(pivot_event, PivotEvent::Expand(..) | PivotEvent::Reborn(..));
+        if self.relating.remove(&level) && !is_relation {
+            self.confirm_expanded(level, &mut events);
+        }
+
+        match pivot_event {
+            PivotEvent::New(pivot) => {
+                let id = self.next_id();
+                events.push(LifecycleEvent::New(id, Object::Pivot(pivot.clone())));
+                self.pivots.insert(level, id);
+                if self.expanded.contains_key(&level) {
+                    self.relating.insert(level);
+                }
+            }
+            PivotEvent::Extend(pivot) => {
+                if let Some(id) = self.pivots.get(&level) {
+                    events.push(LifecycleEvent::Update(*id, Object::Pivot(pivot.clone())));
+                }
+            }
+            PivotEvent::Finish(_, _) => {
+                if let Some(id) = self.pivots.remove(&level) {
+                    events.push(LifecycleEvent::Confirm(ObjectKind::Pivot, id));
+                }
+            }
+            PivotEvent::Leave(_, _) => {}
+            PivotEvent::Expand(_, _, expanded) => {
+                // 继续扩张时更新之前扩张形成的中枢
+                let object = Object::Pivot(expanded.clone());
+                match self.expanded.get(&level) {
+                    Some(id) => events.push(LifecycleEvent::Update(*id, object)),
+                    None => {
+                        let id = self.next_id();
+                        events.push(LifecycleEvent::New(id, object));
+                        self.expanded.insert(level, id);
+                    }
+                }
+            }
+            PivotEvent::Reborn(_, _) => self.confirm_expanded(level, &mut events),
+        }
+        events
+    }
+}
+
+impl Default for LifecycleTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pivot::PivotDetector;
+    use crate::test_util::tests::*;
+
+    #[test]
+    fn test_pen_lifecycle() {
+        let points = make_fractals(&[1.0, 3.0, 2.0, 4.0]);
+        let mut tracker = LifecycleTracker::new();
+        let events = tracker.on_pen_event(&PenEvent::First(points[0].clone(), points[1].clone()));
+        assert_eq!(events.len(), 4);
+        assert!(matches!(events[3], LifecycleEvent::New(2, Object::Pen(_))));
+
+        // 笔延伸，原终点撤销，笔的ID不变
+        let events = tracker.on_pen_event(&PenEvent::UpdateTo(points[3].clone()));
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::Retract(ObjectKind::Point, 1)
+        ));
+        assert!(matches!(
+            events[1],
+            LifecycleEvent::New(3, Object::Point(_))
+        ));
+        match &events[2] {
+            LifecycleEvent::Update(2, Object::Pen(line)) => assert_eq!(line.high(), 4.0),
+            _ => panic!("expect pen update"),
+        }
+
+        // 新笔出现，前一笔确认
+        let events = tracker.on_pen_event(&PenEvent::New(points[2].clone()));
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::Confirm(ObjectKind::Point, 3)
+        ));
+        assert!(matches!(
+            events[1],
+            LifecycleEvent::Confirm(ObjectKind::Pen, 2)
+        ));
+        assert!(matches!(events[3], LifecycleEvent::New(5, Object::Pen(_))));
+    }
+
+    #[test]
+    fn test_fractal_lifecycle() {
+        let points = make_fractals(&[1.0, 3.0]);
+        let mut tracker = LifecycleTracker::new();
+        let events = tracker.on_fractal(&points[0]);
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::New(0, Object::Fractal(_))
+        ));
+        assert!(matches!(
+            events[1],
+            LifecycleEvent::Confirm(ObjectKind::Fractal, 0)
+        ));
+    }
+
+    #[test]
+    fn test_expanded_pivot_lifecycle() {
+        // 中枢B与中枢A波动区间重叠，扩张形成高级别中枢
+        let fractals = make_fractals(&[1.0, 4.0, 2.0, 5.0, 3.0, 6.0, 4.5, 5.5, 4.8]);
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        let mut pivot_events = Vec::new();
+        for w in fractals.windows(2) {
+            pivot_events.extend(pd.on_new_line(Line::new_from_pen(&w[0], &w[1])));
+        }
+        let (pivot, expanded) = pivot_events
+            .iter()
+            .find_map(|e| match e {
+                PivotEvent::Expand(_, pivot, expanded) => Some((pivot.clone(), expanded.clone())),
+                _ => None,
+            })
+            .unwrap();
+
+        let mut tracker = LifecycleTracker::new();
+        let events: Vec<LifecycleEvent> = pivot_events
+            .iter()
+            .flat_map(|e| tracker.on_pivot_event(e))
+            .collect();
+        let id = match events.last().unwrap() {
+            LifecycleEvent::New(id, Object::Pivot(p)) => {
+                assert_eq!(p.level, PivotLevel::Segment);
+                *id
+            }
+            _ => panic!("expect expanded pivot"),
+        };
+
+        // 继续扩张，更新扩张形成的中枢
+        tracker.on_pivot_event(&PivotEvent::New(pivot.clone()));
+        let event = PivotEvent::Expand(pivot.clone(), pivot.clone(), expanded.clone());
+        let events = tracker.on_pivot_event(&event);
+        assert!(matches!(events[..], [LifecycleEvent::Update(i, _)] if i == id));
+
+        // 新中枢之后没有扩张，中枢检测重新开始，扩张形成的中枢确认
+        tracker.on_pivot_event(&PivotEvent::New(pivot.clone()));
+        let events = tracker.on_pivot_event(&PivotEvent::Extend(pivot));
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::Confirm(ObjectKind::Pivot, i) if i == id
+        ));
+    }
+
+    #[test]
+    fn test_segment_lifecycle() {
+        let points = make_fractals(&[1.0, 3.0, 2.0, 4.0, 3.0, 6.0]);
+        let mut tracker = LifecycleTracker::new();
+        let events = tracker.on_segment_state(Some((&points[0], &points[3])));
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::New(0, Object::Segment(_))
+        ));
+        let events = tracker.on_segment_state(Some((&points[0], &points[5])));
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::Update(0, Object::Segment(_))
+        ));
+
+        // 重新寻找线段，撤销正在延伸的线段
+        let events = tracker.on_segment_state(None);
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::Retract(ObjectKind::Segment, 0)
+        ));
+
+        let events = tracker.on_segment_state(Some((&points[2], &points[5])));
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::New(1, Object::Segment(_))
+        ));
+        let event = SegmentEvent::New(points[2].clone(), points[5].clone());
+        let events = tracker.on_segment_event(&event);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            LifecycleEvent::Confirm(ObjectKind::Segment, 1)
+        ));
+    }
+}
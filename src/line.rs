@@ -1,18 +1,34 @@
 use crate::fractal::Fractal;
 use crate::time::Time;
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Point {
     time: Time,
     price: f64,
+    // 分型中间Candle的index，用于计算两点之间的K线数量
+    index: u64,
 }
 
 impl Point {
-    fn new(time: Time, price: f64) -> Self {
-        Self { time, price }
+    fn new(time: Time, price: f64, index: u64) -> Self {
+        Self { time, price, index }
+    }
+
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    pub fn price(&self) -> f64 {
+        self.price
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
     }
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Line {
     from: Point,
     to: Point,
@@ -21,24 +37,26 @@ pub struct Line {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MergeDirection {
     Up,
     Down,
 }
 
 impl Line {
+    // 没有K线信息，index为0
     pub fn new(from_time: Time, from_price: f64, to_time: Time, to_price: f64) -> Self {
         Self {
-            from: Point::new(from_time, from_price),
-            to: Point::new(to_time, to_price),
+            from: Point::new(from_time, from_price, 0),
+            to: Point::new(to_time, to_price, 0),
             extreme_point: None,
             merged: false,
         }
     }
     pub fn new_from_pen(from: &Fractal, to: &Fractal) -> Self {
         Self {
-            from: Point::new(from.time(), from.price()),
-            to: Point::new(to.time(), to.price()),
+            from: Point::new(from.time(), from.price(), from.k2.index),
+            to: Point::new(to.time(), to.price(), to.k2.index),
             extreme_point: None,
             merged: false,
         }
@@ -69,19 +87,22 @@ impl Line {
     }
 
     pub fn is_bottom_fractal(s1: &Line, s2: &Line, s3: &Line) -> bool {
-        if s1.low() > s2.low() && s2.low() > s3.low() {
+        if s1.low() > s2.low() && s2.low() < s3.low() {
             true
         } else {
             false
         }
     }
 
-    pub fn merge(&mut self, rhs: &Line, dir: MergeDirection) -> bool {
+    // 特征序列包含判断，双向包含（前包含与后包含）
+    pub fn is_contain(&self, rhs: &Line) -> bool {
         let is_contain_1 = self.high() < rhs.high() && self.low() > rhs.low();
         let is_contain_2 = self.high() > rhs.high() && self.low() < rhs.low();
-        let is_contain = is_contain_1 || is_contain_2;
+        is_contain_1 || is_contain_2
+    }
 
-        if !is_contain {
+    pub fn merge(&mut self, rhs: &Line, dir: MergeDirection) -> bool {
+        if !self.is_contain(rhs) {
             return false;
         }
 
@@ -93,67 +114,133 @@ impl Line {
         true
     }
 
-    pub fn merge_up(&mut self, rhs: &Line) {
-        let lhs_height = self.to.price - self.from.price;
-        let rhs_height = self.to.price - self.from.price;
-        let is_same =
-            (lhs_height < 0.0 && rhs_height < 0.0) || (lhs_height > 0.0 && rhs_height > 0.0);
-
-        let is_large = (lhs_height.abs() - rhs_height.abs()) > 0.0;
-
-        match (is_large, is_same) {
-            (false, true) => {
-                self.from.time = self.to.time;
-                self.from.price = self.to.price;
-                self.to.time = rhs.from.time;
-                self.to.price = rhs.from.price;
-            }
-            (false, false) => {
-                self.to.time = rhs.from.time;
-                self.to.price = rhs.from.price;
-            }
-            (true, true) => {
-                self.to.time = rhs.to.time;
-                self.to.price = rhs.to.price;
-            }
-            (true, false) => {
-                self.from.time = self.to.time;
-                self.from.price = self.to.price;
-                self.to.time = rhs.to.time;
-                self.to.price = rhs.to.price;
-            }
+    // 只处理前包含，即前面的完全把后面的包含在内
+    pub fn merge_front(&mut self, rhs: &Line, dir: MergeDirection) -> bool {
+        let is_front_contain = self.high() > rhs.high() && self.low() < rhs.low();
+        if !is_front_contain {
+            return false;
         }
+
+        match dir {
+            MergeDirection::Up => self.merge_up(rhs),
+            MergeDirection::Down => self.merge_down(rhs),
+        }
+
+        true
     }
 
+    // 向上合并，高高
+    pub fn merge_up(&mut self, rhs: &Line) {
+        let high = if rhs.high() > self.high() {
+            rhs.high_point()
+        } else {
+            self.high_point()
+        };
+        let low = if rhs.low() > self.low() {
+            rhs.low_point()
+        } else {
+            self.low_point()
+        };
+        self.set_points(high, low);
+    }
+
+    // 向下合并，低低
     pub fn merge_down(&mut self, rhs: &Line) {
-        let lhs_height = self.to.price - self.from.price;
-        let rhs_height = self.to.price - self.from.price;
-        let is_same =
-            (lhs_height < 0.0 && rhs_height < 0.0) || (lhs_height > 0.0 && rhs_height > 0.0);
-
-        let is_large = (lhs_height.abs() - rhs_height.abs()) > 0.0;
-
-        match (is_large, is_same) {
-            (false, true) => {
-                self.from.time = self.to.time;
-                self.from.price = self.to.price;
-                self.to.time = rhs.from.time;
-                self.to.price = rhs.from.price;
-            }
-            (false, false) => {
-                self.to.time = rhs.from.time;
-                self.to.price = rhs.from.price;
-            }
-            (true, true) => {
-                self.to.time = rhs.to.time;
-                self.to.price = rhs.to.price;
-            }
-            (true, false) => {
-                self.from.time = self.to.time;
-                self.from.price = self.to.price;
-                self.to.time = rhs.to.time;
-                self.to.price = rhs.to.price;
-            }
+        let high = if rhs.high() < self.high() {
+            rhs.high_point()
+        } else {
+            self.high_point()
+        };
+        let low = if rhs.low() < self.low() {
+            rhs.low_point()
+        } else {
+            self.low_point()
+        };
+        self.set_points(high, low);
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.to.price > self.from.price
+    }
+
+    // 两点之间包含处理后的K线数量
+    pub fn bars(&self) -> u64 {
+        self.to.index.abs_diff(self.from.index)
+    }
+
+    // 价格变化的斜率，每根K线的价格变化幅度
+    pub fn slope(&self) -> f64 {
+        (self.to.price - self.from.price).abs() / self.bars().max(1) as f64
+    }
+
+    pub fn from(&self) -> Point {
+        self.from
+    }
+
+    pub fn to(&self) -> Point {
+        self.to
+    }
+
+    pub fn high_point(&self) -> Point {
+        if self.from.price > self.to.price {
+            self.from
+        } else {
+            self.to
         }
     }
+
+    pub fn low_point(&self) -> Point {
+        if self.from.price < self.to.price {
+            self.from
+        } else {
+            self.to
+        }
+    }
+
+    // 合并后保持线的方向不变
+    fn set_points(&mut self, high: Point, low: Point) {
+        if self.from.price > self.to.price {
+            self.from = high;
+            self.to = low;
+        } else {
+            self.from = low;
+            self.to = high;
+        }
+        self.merged = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_merge() {
+        // 两个向下笔，前包含，向上合并取高高
+        let mut l1 = Line::new(1, 10.0, 2, 5.0);
+        let l2 = Line::new(3, 9.0, 4, 6.0);
+        assert!(l1.merge(&l2, MergeDirection::Up));
+        assert_eq!(l1.high(), 10.0);
+        assert_eq!(l1.low(), 6.0);
+        assert_eq!(l1.from().time(), 1);
+        assert_eq!(l1.to().time(), 4);
+
+        // 后包含不做前包含处理
+        let mut l3 = Line::new(1, 9.0, 2, 6.0);
+        let l4 = Line::new(3, 10.0, 4, 5.0);
+        assert!(!l3.merge_front(&l4, MergeDirection::Down));
+        assert!(l3.merge(&l4, MergeDirection::Down));
+        assert_eq!(l3.high(), 9.0);
+        assert_eq!(l3.low(), 5.0);
+    }
+
+    #[test]
+    fn test_line_fractal() {
+        let l1 = Line::new(1, 8.0, 2, 5.0);
+        let l2 = Line::new(3, 10.0, 4, 6.0);
+        let l3 = Line::new(5, 9.0, 6, 4.0);
+        assert!(Line::is_top_fractal(&l1, &l2, &l3));
+        assert!(!Line::is_bottom_fractal(&l1, &l2, &l3));
+        assert!(Line::is_bottom_fractal(&l1, &l3, &l2));
+    }
 }
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+
+use crate::bar::Bar;
+use crate::line::Line;
+use crate::time::Time;
+
+// MACD指标
+// DIF = EMA(close, fast) - EMA(close, slow)
+// DEA = EMA(DIF, signal)
+// MACD柱 = (DIF - DEA) * 2，大于0为红柱，小于0为绿柱
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MacdValue {
+    pub dif: f64,
+    pub dea: f64,
+    pub hist: f64,
+}
+
+// 一段时间内红绿柱的面积及峰值，峰值取绝对值
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MacdArea {
+    pub red: f64,
+    pub green: f64,
+    pub red_peak: f64,
+    pub green_peak: f64,
+}
+
+impl MacdArea {
+    // 向上走势看红柱，向下走势看绿柱，返回(面积, 峰值)
+    pub fn strength(&self, is_up: bool) -> (f64, f64) {
+        if is_up {
+            (self.red, self.red_peak)
+        } else {
+            (self.green, self.green_peak)
+        }
+    }
+}
+
+fn ema_alpha(period: usize) -> f64 {
+    2.0 / (period as f64 + 1.0)
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Macd {
+    fast: f64,
+    slow: f64,
+    signal: f64,
+    ema_fast: f64,
+    ema_slow: f64,
+    dea: f64,
+    // 只保留计算面积仍然需要的部分，见trim
+    times: VecDeque<Time>,
+    values: VecDeque<MacdValue>,
+    // 是否已经有过K线，EMA的初始值只取第一根K线
+    started: bool,
+}
+
+impl Macd {
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Self {
+        Self {
+            fast: ema_alpha(fast),
+            slow: ema_alpha(slow),
+            signal: ema_alpha(signal),
+            ema_fast: 0.0,
+            ema_slow: 0.0,
+            dea: 0.0,
+            times: VecDeque::new(),
+            values: VecDeque::new(),
+            started: false,
+        }
+    }
+
+    // 常用参数12, 26, 9
+    pub fn standard() -> Self {
+        Macd::new(12, 26, 9)
+    }
+
+    pub fn on_new_bar(&mut self, bar: &Bar) -> MacdValue {
+        if !self.started {
+            // 以第一根K线的收盘价作为EMA的初始值
+            self.ema_fast = bar.close;
+            self.ema_slow = bar.close;
+            self.started = true;
+        } else {
+            self.ema_fast += self.fast * (bar.close - self.ema_fast);
+            self.ema_slow += self.slow * (bar.close - self.ema_slow);
+        }
+        let dif = self.ema_fast - self.ema_slow;
+        self.dea += self.signal * (dif - self.dea);
+        let value = MacdValue {
+            dif,
+            dea: self.dea,
+            hist: (dif - self.dea) * 2.0,
+        };
+        self.times.push_back(bar.time);
+        self.values.push_back(value);
+        value
+    }
+
+    pub fn last(&self) -> Option<&MacdValue> {
+        self.values.back()
+    }
+
+    // 丢弃time之前的MACD，之后不能再计算这部分的面积
+    pub fn trim(&mut self, time: Time) {
+        let count = self.times.partition_point(|t| *t < time);
+        self.times.drain(..count);
+        self.values.drain(..count);
+    }
+
+    // [from, to]时间段内红绿柱的面积
+    pub fn area(&self, from: Time, to: Time) -> MacdArea {
+        let start = self.times.partition_point(|t| *t < from);
+        let end = self.times.partition_point(|t| *t <= to);
+        let mut area = MacdArea::default();
+        for value in self.values.range(start..end.max(start)) {
+            if value.hist > 0.0 {
+                area.red += value.hist;
+                area.red_peak = area.red_peak.max(value.hist);
+            } else {
+                area.green -= value.hist;
+                area.green_peak = area.green_peak.max(-value.hist);
+            }
+        }
+        area
+    }
+
+    // 笔或者线段对应的红绿柱面积
+    pub fn line_area(&self, line: &Line) -> MacdArea {
+        self.area(line.from().time(), line.to().time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macd() {
+        let mut macd = Macd::standard();
+        let v = macd.on_new_bar(&Bar::new(0, 1.0, 1.0, 1.0, 1.0));
+        assert_eq!(v.dif, 0.0);
+        assert_eq!(v.hist, 0.0);
+
+        // 持续上涨，DIF为正，红柱
+        for i in 1..30 {
+            let price = 1.0 + i as f64 * 0.1;
+            macd.on_new_bar(&Bar::new(i, price, price, price, price));
+        }
+        let v = macd.last().unwrap();
+        assert!(v.dif > 0.0);
+        assert!(v.hist > 0.0);
+
+        let area = macd.area(1, 29);
+        assert!(area.red > 0.0);
+        assert_eq!(area.green, 0.0);
+        assert!(area.red_peak <= area.red);
+        assert_eq!(macd.area(100, 200).red, 0.0);
+
+        // 丢弃之前的部分，不影响之后的面积和新的MACD
+        let last = *macd.last().unwrap();
+        let area = macd.area(20, 29);
+        macd.trim(20);
+        assert_eq!(macd.area(0, 19).red, 0.0);
+        assert_eq!(macd.area(20, 29).red, area.red);
+        assert_eq!(macd.last().unwrap().dif, last.dif);
+        macd.trim(100);
+        let v = macd.on_new_bar(&Bar::new(30, 4.0, 4.0, 4.0, 4.0));
+        assert!(v.dif > 0.0);
+    }
+}
@@ -0,0 +1,332 @@
+use std::fmt;
+
+use crate::analyzer::Analyzer;
+use crate::bar::Bar;
+use crate::divergence::Divergence;
+use crate::line::Point;
+use crate::resample::{Period, Resampler, Session};
+use crate::time::{Time, MINUTE_UNIT};
+
+// 区间套
+// 高级别的背驰，在其最后一段(离开中枢的一段)内寻找低级别的同向背驰，逐级向下确认
+// chain[0]为最高级别的背驰，最后一个为最低级别的背驰，级别越多越可信
+#[derive(Debug, Clone)]
+pub struct NestedLink {
+    // K线周期，毫秒
+    pub period: Time,
+    pub divergence: Divergence,
+}
+
+#[derive(Debug, Clone)]
+pub struct NestedDivergence {
+    pub chain: Vec<NestedLink>,
+    // 发现区间套的K线时间，即最高级别背驰确认的时间
+    pub time: Time,
+}
+
+impl NestedDivergence {
+    // 最低级别背驰段的终点，即区间套定位的转折点
+    pub fn point(&self) -> Point {
+        self.chain.last().unwrap().divergence.leave.to()
+    }
+
+    // 区间套嵌套的级别数量
+    pub fn confidence(&self) -> usize {
+        self.chain.len()
+    }
+}
+
+// 在低级别的背驰中找到位于高级别背驰段内的最后一个同向背驰
+fn link(lower: &[Divergence], higher: &Divergence, period: Time) -> Option<usize> {
+    let from = higher.leave.from().time();
+    // 高级别K线的时间是开始时间，背驰段终点所在的K线结束之前都属于背驰段
+    let to = higher.leave.to().time() + period;
+    lower.iter().rposition(|d| {
+        let time = d.leave.to().time();
+        d.is_up() == higher.is_up() && time >= from && time < to
+    })
+}
+
+#[derive(Debug)]
+struct TimeFrame {
+    period: Time,
+    analyzer: Analyzer,
+    // 由输入K线合成本级别K线，最低级别直接使用输入K线
+    resampler: Option<Resampler>,
+    // 每个背驰在低一级别中对应的背驰
+    links: Vec<Option<usize>>,
+}
+
+impl TimeFrame {
+    fn new(period: Time, base: Time) -> Self {
+        let resampler = if period == base {
+            None
+        } else {
+            let period = Period::Minute(period / MINUTE_UNIT);
+            Some(Resampler::new(base, period, Session::utc()))
+        };
+        Self {
+            period,
+            analyzer: Analyzer::new(),
+            resampler,
+            links: Vec::new(),
+        }
+    }
+
+    fn on_new_bar(&mut self, bar: &Bar) -> Vec<Bar> {
+        match self.resampler.as_mut() {
+            Some(resampler) => resampler.on_new_bar(bar),
+            None => vec![bar.clone()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultiLevelError {
+    // 没有任何级别
+    Empty,
+    // 周期不是正的整数分钟
+    Period(Time),
+    // 高级别的周期必须大于低级别，并且是低级别的整数倍，(低级别, 高级别)
+    Order(Time, Time),
+}
+
+impl fmt::Display for MultiLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultiLevelError::Empty => write!(f, "no level is given"),
+            MultiLevelError::Period(period) => {
+                write!(f, "period {}ms is not a whole number of minutes", period)
+            }
+            MultiLevelError::Order(lower, higher) => write!(
+                f,
+                "period {}ms is not a larger multiple of {}ms",
+                higher, lower
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MultiLevelError {}
+
+#[derive(Debug)]
+pub struct MultiLevelAnalyzer {
+    levels: Vec<TimeFrame>,
+    nested: Vec<NestedDivergence>,
+}
+
+impl MultiLevelAnalyzer {
+    // periods为各级别的K线周期，从小到大，第一个为输入K线的周期
+    pub fn new(periods: &[Time]) -> Result<Self, MultiLevelError> {
+        if periods.is_empty() {
+            return Err(MultiLevelError::Empty);
+        }
+        if let Some(period) = periods.iter().find(|p| **p <= 0 || **p % MINUTE_UNIT != 0) {
+            return Err(MultiLevelError::Period(*period));
+        }
+        if let Some(w) = periods
+            .windows(2)
+            .find(|w| w[0] >= w[1] || w[1] % w[0] != 0)
+        {
+            return Err(MultiLevelError::Order(w[0], w[1]));
+        }
+        Ok(Self {
+            levels: periods
+                .iter()
+                .map(|p| TimeFrame::new(*p, periods[0]))
+                .collect(),
+            nested: Vec::new(),
+        })
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn period(&self, level: usize) -> Time {
+        self.levels[level].period
+    }
+
+    pub fn analyzer(&self, level: usize) -> &Analyzer {
+        &self.levels[level].analyzer
+    }
+
+    pub fn nested(&self) -> &[NestedDivergence] {
+        &self.nested
+    }
+
+    // 从level级别的第index个背驰开始，沿着链接向下构造区间套
+    fn chain(&self, level: usize, index: usize) -> Vec<NestedLink> {
+        let mut chain = Vec::new();
+        let mut current = Some(index);
+        for l in (0..=level).rev() {
+            let index = match current {
+                Some(index) => index,
+                None => break,
+            };
+            let tf = &self.levels[l];
+            chain.push(NestedLink {
+                period: tf.period,
+                divergence: tf.analyzer.divergences()[index].clone(),
+            });
+            current = tf.links.get(index).copied().flatten();
+        }
+        chain
+    }
+
+    fn on_level_bar(&mut self, level: usize, bar: &Bar, time: Time) {
+        let count = self.levels[level].analyzer.divergences().len();
+        self.levels[level].analyzer.on_new_bar(bar);
+        let total = self.levels[level].analyzer.divergences().len();
+        for index in count..total {
+            let linked = if level == 0 {
+                None
+            } else {
+                let lower = &self.levels[level - 1];
+                let higher = &self.levels[level];
+                link(
+                    lower.analyzer.divergences(),
+                    &higher.analyzer.divergences()[index],
+                    higher.period,
+                )
+            };
+            self.levels[level].links.push(linked);
+            if linked.is_some() {
+                let nested = NestedDivergence {
+                    chain: self.chain(level, index),
+                    time,
+                };
+                self.nested.push(nested);
+            }
+        }
+    }
+
+    pub fn on_new_bar(&mut self, bar: &Bar) {
+        // 从低级别到高级别，保证高级别背驰出现时低级别已经处理过同一根K线
+        for level in 0..self.levels.len() {
+            for completed in self.levels[level].on_new_bar(bar) {
+                self.on_level_bar(level, &completed, bar.time);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line::Line;
+    use crate::macd::MacdArea;
+    use crate::pivot::{Pivot, PivotDetector, PivotLevel};
+    use crate::test_util::tests::*;
+    use crate::time::MINUTE_UNIT;
+
+    fn pivot() -> Pivot {
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        let fractals = make_fractals(&[1.0, 4.0, 2.0, 3.0, 2.5]);
+        for w in fractals.windows(2) {
+            pd.on_new_line(Line::new_from_pen(&w[0], &w[1]));
+        }
+        pd.pivot().unwrap().clone()
+    }
+
+    fn divergence(from: Time, to: Time, up: bool) -> Divergence {
+        let (p0, p1) = if up { (2.5, 5.0) } else { (2.5, 0.5) };
+        Divergence {
+            level: PivotLevel::Pen,
+            pivot: pivot(),
+            enter: Line::new(0, 1.0, 1, 4.0),
+            enter_area: MacdArea::default(),
+            leave: Line::new(from, p0, to, p1),
+            leave_area: MacdArea::default(),
+        }
+    }
+
+    #[test]
+    fn test_link() {
+        let m = MINUTE_UNIT;
+        let lower = vec![
+            divergence(10 * m, 20 * m, true),
+            divergence(20 * m, 32 * m, false),
+            divergence(25 * m, 34 * m, true),
+            divergence(30 * m, 36 * m, true),
+        ];
+        // 高级别背驰段[5m, 30m]，30m处的K线到35m结束
+        let higher = divergence(5 * m, 30 * m, true);
+        assert_eq!(link(&lower, &higher, 5 * m), Some(2));
+        let higher = divergence(5 * m, 30 * m, false);
+        assert_eq!(link(&lower, &higher, 5 * m), Some(1));
+        let higher = divergence(40 * m, 50 * m, true);
+        assert_eq!(link(&lower, &higher, 5 * m), None);
+    }
+
+    #[test]
+    fn test_resample() {
+        let mut tf = TimeFrame::new(5 * MINUTE_UNIT, MINUTE_UNIT);
+        let mut bars = Vec::new();
+        for i in 0..12 {
+            let price = i as f64;
+            let bar = Bar::new(
+                i * MINUTE_UNIT,
+                price,
+                price + 1.0,
+                price - 1.0,
+                price + 0.5,
+            );
+            bars.extend(tf.on_new_bar(&bar));
+        }
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].time, 0);
+        assert_eq!((bars[0].open, bars[0].high, bars[0].low), (0.0, 5.0, -1.0));
+        assert_eq!(bars[0].close, 4.5);
+        assert_eq!(bars[1].time, 5 * MINUTE_UNIT);
+
+        // 数据中断，之前未完成的K线不能丢弃
+        let bars = tf.on_new_bar(&Bar::new(20 * MINUTE_UNIT, 20.0, 21.0, 19.0, 20.5));
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].time, 10 * MINUTE_UNIT);
+        assert_eq!((bars[0].open, bars[0].high, bars[0].low), (10.0, 12.0, 9.0));
+        assert_eq!(bars[0].close, 11.5);
+    }
+
+    #[test]
+    fn test_new() {
+        let m = MINUTE_UNIT;
+        assert!(MultiLevelAnalyzer::new(&[m, 5 * m, 30 * m]).is_ok());
+        let err = |periods: &[Time]| MultiLevelAnalyzer::new(periods).unwrap_err();
+        assert_eq!(err(&[]), MultiLevelError::Empty);
+        assert_eq!(err(&[m, 90_000]), MultiLevelError::Period(90_000));
+        assert_eq!(err(&[0, m]), MultiLevelError::Period(0));
+        assert_eq!(err(&[5 * m, m]), MultiLevelError::Order(5 * m, m));
+        assert_eq!(err(&[m, m]), MultiLevelError::Order(m, m));
+        assert_eq!(err(&[2 * m, 5 * m]), MultiLevelError::Order(2 * m, 5 * m));
+    }
+
+    #[test]
+    fn test_multi_level_analyzer() {
+        let bars = load_eurusd_2021();
+        let m = MINUTE_UNIT;
+        // 样本数据只有三个月，30分钟级别没有背驰，用较小的周期得到三个级别的区间套
+        let mut analyzer = MultiLevelAnalyzer::new(&[m, 2 * m, 6 * m]).unwrap();
+        for bar in &bars {
+            analyzer.on_new_bar(bar);
+        }
+        let nested = analyzer.nested();
+        assert!(!nested.is_empty());
+        assert!(nested
+            .iter()
+            .any(|n| n.confidence() == analyzer.level_count()));
+        for n in nested {
+            assert!(n.confidence() >= 2);
+            let up = n.chain[0].divergence.is_up();
+            for w in n.chain.windows(2) {
+                let (parent, child) = (&w[0], &w[1]);
+                assert!(parent.period > child.period);
+                assert_eq!(child.divergence.is_up(), up);
+                // 低级别背驰段的终点位于高级别背驰段内
+                let time = child.divergence.leave.to().time();
+                assert!(time >= parent.divergence.leave.from().time());
+                assert!(time < parent.divergence.leave.to().time() + parent.period);
+            }
+        }
+    }
+}
@@ -101,11 +101,36 @@ pub fn _merge_same_type(f1: &Fractal, f2: &Fractal) -> MergeAction {
     }
 }
 
-// TODO: 后分型包含前分型的情况需要处理
+// 笔的划分规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PenRule {
+    // 老笔：包含处理之后，顶底分型之间至少有一根独立K线
+    Standard,
+    // 新笔：顶底分型不共用K线即可
+    Relaxed,
+}
+
+impl PenRule {
+    // 顶底分型中间K线的最小距离
+    fn min_distance(&self) -> u64 {
+        match self {
+            PenRule::Standard => 4,
+            PenRule::Relaxed => 3,
+        }
+    }
+}
+
 pub fn is_pen(f1: &Fractal, f2: &Fractal) -> bool {
+    is_pen_with_rule(f1, f2, PenRule::Standard)
+}
+
+// TODO: 后分型包含前分型的情况需要处理
+pub fn is_pen_with_rule(f1: &Fractal, f2: &Fractal, rule: PenRule) -> bool {
+    let has_enough_distance = f1.distance(f2) >= rule.min_distance();
     if f1.fractal_type() == FractalType::Top
         && f2.fractal_type() == FractalType::Bottom
-        && f1.has_enough_distance(f2)
+        && has_enough_distance
         && f2.lowest() < f1.lowest()
         && !f1.is_contain(f2)
     //&& !f2.is_contain(f1)
@@ -115,7 +140,7 @@ pub fn is_pen(f1: &Fractal, f2: &Fractal) -> bool {
 
     if f1.fractal_type() == FractalType::Bottom
         && f2.fractal_type() == FractalType::Top
-        && f1.has_enough_distance(f2)
+        && has_enough_distance
         && f2.highest() > f1.highest()
         && !f1.is_contain(f2)
     //&& !f2.is_contain(f1)
@@ -127,6 +152,7 @@ pub fn is_pen(f1: &Fractal, f2: &Fractal) -> bool {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PenEvent {
     First(Fractal, Fractal),
     New(Fractal),
@@ -134,31 +160,40 @@ pub enum PenEvent {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PenDirection {
     Up,
     Down,
 }
 
 // TODO:考虑一种特殊情况就是顶分型高点相等或者底分型低点相等
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PenDetector {
     window: RingBuffer<Fractal>,
     has_pen: bool,
+    rule: PenRule,
 }
 
 impl PenDetector {
     pub fn new() -> Self {
+        Self::with_rule(PenRule::Standard)
+    }
+
+    pub fn with_rule(rule: PenRule) -> Self {
         Self {
             window: RingBuffer::new(3),
             has_pen: false,
+            rule,
         }
     }
 
     fn _is_pen(&self, start_index: usize) -> bool {
         debug_assert!(self.window.len() >= 2 + start_index);
-        is_pen(
+        is_pen_with_rule(
             self.window.get(start_index as isize).unwrap(),
             self.window.get((start_index + 1) as isize).unwrap(),
+            self.rule,
         )
     }
 
@@ -207,7 +242,7 @@ impl PenDetector {
         debug_assert!(self.window.len() == 2);
 
         let b = self.window.get(-1).unwrap();
-        let bc_is_pen = is_pen(b, &f);
+        let bc_is_pen = is_pen_with_rule(b, &f, self.rule);
         if bc_is_pen {
             // 2.1
             self.window.push(f);
@@ -256,7 +291,7 @@ impl PenDetector {
         debug_assert!(self.window.len() == 2);
 
         let b = self.window.get(-1).unwrap();
-        let bc_is_pen = is_pen(b, &f);
+        let bc_is_pen = is_pen_with_rule(b, &f, self.rule);
         if bc_is_pen {
             // 3.1
             let c = f.clone();
@@ -291,10 +326,7 @@ impl PenDetector {
             .get(-2)
             .unwrap()
             .is_same_type(self.window.get(-1).unwrap()));
-        debug_assert!(!is_pen(
-            self.window.get(-2).unwrap(),
-            self.window.get(-1).unwrap()
-        ));
+        debug_assert!(!self.bc_is_pen());
         debug_assert!(self.has_pen);
         debug_assert!(self.window.len() == 3);
 
@@ -379,6 +411,15 @@ mod tests {
         let is_pen = is_pen(&f1, &f2);
         assert!(is_pen);
     }
+
+    #[test]
+    fn test_pen_rule() {
+        // 中间K线的距离为3，顶底分型不共用K线
+        let f1 = make_fractal(0, 10.0, true);
+        let f2 = make_fractal(1, 5.0, false);
+        assert!(!is_pen_with_rule(&f1, &f2, PenRule::Standard));
+        assert!(is_pen_with_rule(&f1, &f2, PenRule::Relaxed));
+    }
     #[test]
     fn test_pen_detector_with_candle() {
         let (bars, candles, fractals) = load_fractal();
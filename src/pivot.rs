@@ -1,3 +1,563 @@
+use crate::fractal::Fractal;
+use crate::line::Line;
+use crate::pen_detector::PenEvent;
+use crate::ringbuffer::RingBuffer;
+use crate::sd2::SegmentEvent;
+use crate::time::Time;
+
+// 中枢级别，笔中枢由笔构成，线段中枢由线段构成
+// 同级别中枢扩张，形成高一级别的中枢
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PivotLevel {
+    Pen,
+    Segment,
+    // 线段中枢之上的级别，数值越大级别越高
+    Higher(u32),
+}
+
+impl PivotLevel {
+    pub fn upgrade(&self) -> Self {
+        match self {
+            PivotLevel::Pen => PivotLevel::Segment,
+            PivotLevel::Segment => PivotLevel::Higher(1),
+            PivotLevel::Higher(n) => PivotLevel::Higher(n + 1),
+        }
+    }
+
+    // 0为笔中枢，1为线段中枢，更高的级别依次增加
+    pub fn as_index(&self) -> u32 {
+        match self {
+            PivotLevel::Pen => 0,
+            PivotLevel::Segment => 1,
+            PivotLevel::Higher(n) => n + 1,
+        }
+    }
+}
+
 // 中枢
-#[derive(Debug)]
-struct Pivot {}
+// 连续三个次级别走势(笔)的重叠部分构成中枢
+// ZG = min(g1, g2, g3)，ZD = max(d1, d2, d3)，ZG > ZD 中枢成立
+// GG = max(gn)，DD = min(dn)，为中枢波动的最高最低点
+// 中枢前一笔为进入中枢的一笔，要求从中枢区间外进入，否则单边走势中任意连续三笔都有重叠
+// 中枢成立后，后续的笔只要与[ZD, ZG]有重叠，就是中枢的延伸
+// 出现一笔与[ZD, ZG]没有重叠，中枢结束，其前一笔为离开中枢的一笔
+// 中枢结束后出现的同级别新中枢，与前中枢的关系
+// 1. 扩张：两个中枢的波动区间[DD, GG]有重叠，合并为高一级别的中枢
+// 2. 新生：两个中枢的波动区间没有重叠
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pivot {
+    pub level: PivotLevel,
+    pub zg: f64,
+    pub zd: f64,
+    pub gg: f64,
+    pub dd: f64,
+    pub start: Time,
+    pub end: Time,
+    // 构成中枢的次级别走势(笔或线段)数量
+    pub count: usize,
+    // 进入中枢的一笔(线段)
+    pub enter: Line,
+}
+
+impl Pivot {
+    fn new(level: PivotLevel, enter: &Line, l1: &Line, l2: &Line, l3: &Line) -> Option<Pivot> {
+        let zg = l1.high().min(l2.high()).min(l3.high());
+        let zd = l1.low().max(l2.low()).max(l3.low());
+        if zg <= zd {
+            return None;
+        }
+
+        // 进入中枢的一笔，起点必须在中枢区间之外
+        let from = enter.from().price();
+        let is_enter_up = from < enter.to().price();
+        if (is_enter_up && from >= zd) || (!is_enter_up && from <= zg) {
+            return None;
+        }
+
+        Some(Self {
+            level,
+            zg,
+            zd,
+            gg: l1.high().max(l2.high()).max(l3.high()),
+            dd: l1.low().min(l2.low()).min(l3.low()),
+            start: l1.from().time(),
+            end: l3.to().time(),
+            count: 3,
+            enter: *enter,
+        })
+    }
+
+    // 是否与中枢区间[ZD, ZG]重叠
+    pub fn is_overlap(&self, line: &Line) -> bool {
+        line.low() < self.zg && line.high() > self.zd
+    }
+
+    // 两个中枢的波动区间[DD, GG]是否重叠
+    pub fn is_range_overlap(&self, other: &Pivot) -> bool {
+        other.dd < self.gg && other.gg > self.dd
+    }
+
+    // 中枢扩张，新中枢区间为两个中枢波动区间的重叠部分
+    pub(crate) fn merge(&self, other: &Pivot) -> Pivot {
+        Self {
+            level: self.level,
+            zg: self.gg.min(other.gg),
+            zd: self.dd.max(other.dd),
+            gg: self.gg.max(other.gg),
+            dd: self.dd.min(other.dd),
+            start: self.start,
+            end: other.end,
+            count: self.count + other.count,
+            enter: self.enter,
+        }
+    }
+
+    fn extend(&mut self, line: &Line) {
+        self.gg = self.gg.max(line.high());
+        self.dd = self.dd.min(line.low());
+        self.end = line.to().time();
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PivotEvent {
+    // 中枢形成
+    New(Pivot),
+    // 中枢延伸
+    Extend(Pivot),
+    // 已确认的一笔终点在中枢区间之外，如果下一笔不再回到中枢，就是离开中枢的一笔
+    Leave(Pivot, Line),
+    // 中枢结束，附带离开中枢的一笔
+    Finish(Pivot, Option<Line>),
+    // 中枢扩张，(前中枢, 新中枢, 扩张后的高级别中枢)
+    Expand(Pivot, Pivot, Pivot),
+    // 中枢新生，(前中枢, 新中枢)
+    Reborn(Pivot, Pivot),
+}
+
+// 基于已确认的次级别走势检测中枢
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PivotDetector {
+    level: PivotLevel,
+    // 中枢形成前，保存最近的4笔，第一笔为进入中枢的一笔
+    window: RingBuffer<Line>,
+    pivot: Option<Pivot>,
+    // 与中枢重叠，但还不能确定是中枢的一部分还是离开中枢的一笔
+    pending: Option<Line>,
+    // 前一个已经结束的中枢
+    last: Option<Pivot>,
+    // 连续扩张形成的高级别中枢，出现新生后清除
+    expanded: Option<Pivot>,
+}
+
+impl PivotDetector {
+    pub fn new(level: PivotLevel) -> Self {
+        Self {
+            level,
+            window: RingBuffer::new(4),
+            pivot: None,
+            pending: None,
+            last: None,
+            expanded: None,
+        }
+    }
+
+    pub fn pivot(&self) -> Option<&Pivot> {
+        self.pivot.as_ref()
+    }
+
+    pub fn expanded(&self) -> Option<&Pivot> {
+        self.expanded.as_ref()
+    }
+
+    // 之后的中枢事件可能用到的最早时间，即进入中枢的一笔或者可能成为进入段的一笔的起点
+    // 还没有任何笔时返回None
+    pub fn earliest(&self) -> Option<Time> {
+        let enter = self.pivot.as_ref().map(|p| p.enter.from().time());
+        let window = self.window.get(0).map(|l| l.from().time());
+        enter.into_iter().chain(window).min()
+    }
+
+    // 新中枢与前中枢的关系
+    fn relate(&mut self, pivot: &Pivot) -> Option<PivotEvent> {
+        let last = self.last.clone()?;
+        if last.is_range_overlap(pivot) {
+            let expanded = match self.expanded.take() {
+                // 继续扩张，级别不变
+                Some(expanded) => expanded.merge(pivot),
+                None => {
+                    let mut expanded = last.merge(pivot);
+                    expanded.level = last.level.upgrade();
+                    expanded
+                }
+            };
+            self.expanded = Some(expanded.clone());
+            Some(PivotEvent::Expand(last, pivot.clone(), expanded))
+        } else {
+            self.expanded = None;
+            Some(PivotEvent::Reborn(last, pivot.clone()))
+        }
+    }
+
+    fn find_pivot(&mut self, line: Line) -> Vec<PivotEvent> {
+        let mut events = Vec::new();
+        self.window.push(line);
+        if self.window.len() < 4 {
+            return events;
+        }
+
+        let pivot = Pivot::new(
+            self.level,
+            self.window.get(0).unwrap(),
+            self.window.get(1).unwrap(),
+            self.window.get(2).unwrap(),
+            self.window.get(3).unwrap(),
+        );
+        if let Some(pivot) = pivot {
+            self.window.clear();
+            self.pivot = Some(pivot.clone());
+            let relation = self.relate(&pivot);
+            events.push(PivotEvent::New(pivot));
+            events.extend(relation);
+        }
+        events
+    }
+
+    // 次级别走势不连续时重新开始，结束当前中枢，之前的走势不再参与中枢的构成
+    pub fn restart(&mut self) -> Vec<PivotEvent> {
+        self.window.clear();
+        self.pending = None;
+        self.last = None;
+        self.expanded = None;
+        match self.pivot.take() {
+            Some(pivot) => vec![PivotEvent::Finish(pivot, None)],
+            None => Vec::new(),
+        }
+    }
+
+    pub fn on_new_line(&mut self, line: Line) -> Vec<PivotEvent> {
+        let pivot = match self.pivot.as_mut() {
+            Some(pivot) => pivot,
+            None => return self.find_pivot(line),
+        };
+
+        if pivot.is_overlap(&line) {
+            // 当前笔回到中枢，前一笔是中枢的一部分
+            let mut events = Vec::new();
+            let pending = self.pending.replace(line);
+            if let Some(prev) = pending {
+                pivot.extend(&prev);
+                events.push(PivotEvent::Extend(pivot.clone()));
+            }
+            let to = line.to().price();
+            if to > pivot.zg || to < pivot.zd {
+                events.push(PivotEvent::Leave(pivot.clone(), line));
+            }
+            events
+        } else {
+            // 当前笔与中枢没有重叠，中枢结束，从离开中枢的一笔开始寻找新中枢
+            let pivot = self.pivot.take().unwrap();
+            let leave = self.pending.take();
+            if let Some(leave) = leave {
+                self.window.push(leave);
+            }
+            self.window.push(line);
+            self.last = Some(pivot.clone());
+            vec![PivotEvent::Finish(pivot, leave)]
+        }
+    }
+}
+
+// 笔中枢，只处理已经确认的笔
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PenPivotDetector {
+    points: RingBuffer<Fractal>,
+    detector: PivotDetector,
+}
+
+impl PenPivotDetector {
+    pub fn new() -> Self {
+        Self {
+            points: RingBuffer::new(2),
+            detector: PivotDetector::new(PivotLevel::Pen),
+        }
+    }
+
+    pub fn pivot(&self) -> Option<&Pivot> {
+        self.detector.pivot()
+    }
+
+    pub fn expanded(&self) -> Option<&Pivot> {
+        self.detector.expanded()
+    }
+
+    // 之后的中枢事件可能用到的最早时间，之后的笔从最后一笔的起点开始
+    pub fn earliest(&self) -> Option<Time> {
+        let start = self.points.get(0)?.time();
+        Some(self.detector.earliest().map_or(start, |t| t.min(start)))
+    }
+
+    pub fn on_pen_event(&mut self, pen_event: &PenEvent) -> Vec<PivotEvent> {
+        match pen_event {
+            PenEvent::First(a, b) => {
+                self.points.push(a.clone());
+                self.points.push(b.clone());
+                Vec::new()
+            }
+
+            PenEvent::New(c) => {
+                // 新笔出现，前一笔确认
+                let line =
+                    Line::new_from_pen(self.points.get(0).unwrap(), self.points.get(1).unwrap());
+                self.points.push(c.clone());
+                self.detector.on_new_line(line)
+            }
+
+            PenEvent::UpdateTo(c) => {
+                self.points.pop_back();
+                self.points.push(c.clone());
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl Default for PenPivotDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 线段中枢，SegmentEvent发出的线段都是已经确认的
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SegmentPivotDetector {
+    detector: PivotDetector,
+    // 前一个线段的终点
+    end: Option<Time>,
+}
+
+impl SegmentPivotDetector {
+    pub fn new() -> Self {
+        Self {
+            detector: PivotDetector::new(PivotLevel::Segment),
+            end: None,
+        }
+    }
+
+    pub fn pivot(&self) -> Option<&Pivot> {
+        self.detector.pivot()
+    }
+
+    pub fn expanded(&self) -> Option<&Pivot> {
+        self.detector.expanded()
+    }
+
+    // 之后的中枢事件可能用到的最早时间，之后的线段从前一个线段的终点或者更晚开始
+    pub fn earliest(&self) -> Option<Time> {
+        let end = self.end?;
+        Some(self.detector.earliest().map_or(end, |t| t.min(end)))
+    }
+
+    fn on_new_segment(&mut self, from: &Fractal, to: &Fractal) -> Vec<PivotEvent> {
+        let mut events = Vec::new();
+        if self.end.is_some_and(|end| end != from.time()) {
+            // 线段不连续(线段检测重新寻找第一个线段)，之前的线段不能与新线段构成中枢
+            events.extend(self.detector.restart());
+        }
+        self.end = Some(to.time());
+        events.extend(self.detector.on_new_line(Line::new_from_pen(from, to)));
+        events
+    }
+
+    pub fn on_segment_event(&mut self, segment_event: &SegmentEvent) -> Vec<PivotEvent> {
+        match segment_event {
+            SegmentEvent::New(a, b) => self.on_new_segment(a, b),
+
+            SegmentEvent::New2(a, b, c) => {
+                // 同时确认两个线段
+                let mut events = self.on_new_segment(a, b);
+                events.extend(self.on_new_segment(b, c));
+                events
+            }
+        }
+    }
+}
+
+impl Default for SegmentPivotDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::*;
+
+    fn feed(pd: &mut PenPivotDetector, prices: &[f64]) -> Vec<PivotEvent> {
+        let points = make_fractals(prices);
+        let mut events = Vec::new();
+        pd.on_pen_event(&PenEvent::First(points[0].clone(), points[1].clone()));
+        for p in points.iter().skip(2) {
+            events.extend(pd.on_pen_event(&PenEvent::New(p.clone())));
+        }
+        events
+    }
+
+    fn feed_lines(pd: &mut PivotDetector, prices: &[f64]) -> Vec<PivotEvent> {
+        let mut events = Vec::new();
+        for i in 1..prices.len() {
+            let line = Line::new(i as i64 - 1, prices[i - 1], i as i64, prices[i]);
+            events.extend(pd.on_new_line(line));
+        }
+        events
+    }
+
+    #[test]
+    fn test_pivot_expand() {
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        // 中枢A [3, 4]，波动区间 [2, 5]，3-6离开，6-4.5不回中枢
+        // 中枢B [4.8, 5.5]，波动区间 [4.5, 6]，与A的波动区间重叠
+        let events = feed_lines(&mut pd, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0, 4.5, 5.5, 4.8]);
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[2], PivotEvent::Finish(_, _)));
+        match &events[4] {
+            PivotEvent::Expand(a, b, expanded) => {
+                assert_eq!(a.gg, 5.0);
+                assert_eq!(b.dd, 4.5);
+                assert_eq!(expanded.level, PivotLevel::Segment);
+                assert_eq!(expanded.zg, 5.0);
+                assert_eq!(expanded.zd, 4.5);
+                assert_eq!(expanded.gg, 6.0);
+                assert_eq!(expanded.dd, 2.0);
+            }
+            _ => panic!("expect PivotEvent::Expand"),
+        }
+        assert!(pd.expanded().is_some());
+    }
+
+    #[test]
+    fn test_pivot_reborn() {
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        let events = feed_lines(&mut pd, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0, 5.5, 7.0, 5.8]);
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[3], PivotEvent::New(_)));
+        match &events[4] {
+            PivotEvent::Reborn(a, b) => {
+                assert_eq!(a.zg, 4.0);
+                assert_eq!(b.zg, 6.0);
+                assert_eq!(b.zd, 5.8);
+            }
+            _ => panic!("expect PivotEvent::Reborn"),
+        }
+        assert!(pd.expanded().is_none());
+    }
+
+    #[test]
+    fn test_pen_pivot() {
+        let mut pd = PenPivotDetector::new();
+        // 1-4进入中枢，4-2, 2-5, 5-3 重叠 [3, 4]
+        // 3-4.5, 4.5-3.5 回到中枢，3.5-6离开中枢，6-5不再回到中枢
+        let events = feed(&mut pd, &[1.0, 4.0, 2.0, 5.0, 3.0, 4.5, 3.5, 6.0, 5.0, 7.0]);
+        assert_eq!(events.len(), 6);
+        match &events[0] {
+            PivotEvent::New(pivot) => {
+                assert_eq!(pivot.level, PivotLevel::Pen);
+                assert_eq!(pivot.zg, 4.0);
+                assert_eq!(pivot.zd, 3.0);
+                assert_eq!(pivot.gg, 5.0);
+                assert_eq!(pivot.dd, 2.0);
+                assert_eq!(pivot.count, 3);
+            }
+            _ => panic!("expect PivotEvent::New"),
+        }
+        match &events[1] {
+            PivotEvent::Leave(_, line) => {
+                assert_eq!(line.high(), 4.5);
+            }
+            _ => panic!("expect PivotEvent::Leave"),
+        }
+        match &events[3] {
+            PivotEvent::Extend(pivot) => {
+                assert_eq!(pivot.count, 5);
+            }
+            _ => panic!("expect PivotEvent::Extend"),
+        }
+        assert!(matches!(events[4], PivotEvent::Leave(_, _)));
+        match &events[5] {
+            PivotEvent::Finish(pivot, leave) => {
+                assert_eq!(pivot.count, 5);
+                assert_eq!(pivot.gg, 5.0);
+                let leave = leave.unwrap();
+                assert_eq!(leave.low(), 3.5);
+                assert_eq!(leave.high(), 6.0);
+            }
+            _ => panic!("expect PivotEvent::Finish"),
+        }
+        assert!(pd.pivot().is_none());
+    }
+
+    #[test]
+    fn test_segment_pivot() {
+        let points = make_fractals(&[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+        let mut sd = SegmentPivotDetector::new();
+        let mut events = Vec::new();
+        events
+            .extend(sd.on_segment_event(&SegmentEvent::New(points[0].clone(), points[1].clone())));
+        events.extend(sd.on_segment_event(&SegmentEvent::New2(
+            points[1].clone(),
+            points[2].clone(),
+            points[3].clone(),
+        )));
+        assert!(events.is_empty());
+        events
+            .extend(sd.on_segment_event(&SegmentEvent::New(points[3].clone(), points[4].clone())));
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            PivotEvent::New(pivot) => {
+                assert_eq!(pivot.level, PivotLevel::Segment);
+                assert_eq!(pivot.zg, 4.0);
+                assert_eq!(pivot.zd, 3.0);
+            }
+            _ => panic!("expect PivotEvent::New"),
+        }
+        assert!(sd.pivot().is_some());
+    }
+
+    #[test]
+    fn test_segment_pivot_restart() {
+        let points = make_fractals(&[1.0, 4.0, 2.0, 5.0, 3.0, 6.0, 3.5, 4.5]);
+        let segment = |i: usize, j: usize| SegmentEvent::New(points[i].clone(), points[j].clone());
+        let mut sd = SegmentPivotDetector::new();
+        let mut events = Vec::new();
+        for i in 0..3 {
+            events.extend(sd.on_segment_event(&segment(i, i + 1)));
+        }
+        // 线段检测重新开始，新线段不从前一线段的终点开始，不能与之前的线段构成中枢
+        events.extend(sd.on_segment_event(&segment(4, 5)));
+        assert!(events.is_empty());
+        assert!(sd.pivot().is_none());
+
+        for i in 5..7 {
+            events.extend(sd.on_segment_event(&segment(i, i + 1)));
+        }
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_no_pivot_in_trend() {
+        let mut pd = PenPivotDetector::new();
+        let events = feed(
+            &mut pd,
+            &[10.0, 12.0, 8.0, 9.0, 6.0, 7.0, 4.0, 5.0, 2.0, 3.0],
+        );
+        assert!(events.is_empty());
+    }
+}
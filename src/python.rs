@@ -0,0 +1,208 @@
+use numpy::{IntoPyArray, PyReadonlyArray1};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::analyzer::Analyzer;
+use crate::bar::Bar;
+use crate::fractal::{Fractal, FractalType};
+use crate::lifecycle::Tracked;
+use crate::line::Line;
+use crate::pivot::Pivot;
+
+// Python绑定，用maturin编译：maturin develop --release
+// 时间为毫秒(int64)，pandas的DatetimeIndex可以用index.asi8 // 10**6转换
+// 返回值为{列名: numpy数组}的dict，可以直接传给pandas.DataFrame
+
+fn column<'py, T: numpy::Element>(
+    dict: &Bound<'py, PyDict>,
+    name: &str,
+    values: Vec<T>,
+) -> PyResult<()> {
+    dict.set_item(name, values.into_pyarray(dict.py()))
+}
+
+// type: 1为顶分型，-1为底分型
+fn fractal_columns<'py>(
+    py: Python<'py>,
+    fractals: &[Tracked<Fractal>],
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    column(&dict, "id", fractals.iter().map(|f| f.id).collect())?;
+    column(
+        &dict,
+        "time",
+        fractals.iter().map(|f| f.value.time()).collect(),
+    )?;
+    column(
+        &dict,
+        "price",
+        fractals.iter().map(|f| f.value.price()).collect(),
+    )?;
+    let types = fractals.iter().map(|f| match f.value.fractal_type() {
+        FractalType::Top => 1i8,
+        FractalType::Bottom => -1i8,
+    });
+    column(&dict, "type", types.collect())?;
+    column(
+        &dict,
+        "confirmed",
+        fractals.iter().map(|f| f.confirmed).collect(),
+    )?;
+    Ok(dict)
+}
+
+fn line_columns<'py>(py: Python<'py>, lines: &[Tracked<Line>]) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    column(&dict, "id", lines.iter().map(|l| l.id).collect())?;
+    column(
+        &dict,
+        "from_time",
+        lines.iter().map(|l| l.value.from().time()).collect(),
+    )?;
+    column(
+        &dict,
+        "from_price",
+        lines.iter().map(|l| l.value.from().price()).collect(),
+    )?;
+    column(
+        &dict,
+        "to_time",
+        lines.iter().map(|l| l.value.to().time()).collect(),
+    )?;
+    column(
+        &dict,
+        "to_price",
+        lines.iter().map(|l| l.value.to().price()).collect(),
+    )?;
+    column(
+        &dict,
+        "confirmed",
+        lines.iter().map(|l| l.confirmed).collect(),
+    )?;
+    Ok(dict)
+}
+
+// level: 0为笔中枢，1为线段中枢，更高的级别依次增加
+// 已经结束的中枢在前，正在延伸的笔中枢和线段中枢在后(confirmed为False)
+fn pivot_columns<'py>(py: Python<'py>, analyzer: &Analyzer) -> PyResult<Bound<'py, PyDict>> {
+    let pivots: Vec<(&Pivot, bool)> = analyzer
+        .pivots()
+        .iter()
+        .map(|p| (p, true))
+        .chain(analyzer.pen_pivot().map(|p| (p, false)))
+        .chain(analyzer.segment_pivot().map(|p| (p, false)))
+        .collect();
+    let dict = PyDict::new(py);
+    column(
+        &dict,
+        "level",
+        pivots.iter().map(|(p, _)| p.level.as_index()).collect(),
+    )?;
+    column(
+        &dict,
+        "start",
+        pivots.iter().map(|(p, _)| p.start).collect(),
+    )?;
+    column(&dict, "end", pivots.iter().map(|(p, _)| p.end).collect())?;
+    column(&dict, "zg", pivots.iter().map(|(p, _)| p.zg).collect())?;
+    column(&dict, "zd", pivots.iter().map(|(p, _)| p.zd).collect())?;
+    column(&dict, "gg", pivots.iter().map(|(p, _)| p.gg).collect())?;
+    column(&dict, "dd", pivots.iter().map(|(p, _)| p.dd).collect())?;
+    column(
+        &dict,
+        "count",
+        pivots.iter().map(|(p, _)| p.count as u64).collect(),
+    )?;
+    column(&dict, "confirmed", pivots.iter().map(|(_, c)| *c).collect())?;
+    Ok(dict)
+}
+
+// Analyzer内部的订阅者不是Send，只能在创建它的线程中使用
+#[pyclass(name = "Analyzer", unsendable)]
+pub struct PyAnalyzer {
+    analyzer: Analyzer,
+}
+
+#[pymethods]
+impl PyAnalyzer {
+    #[new]
+    fn new() -> Self {
+        Self {
+            analyzer: Analyzer::new(),
+        }
+    }
+
+    fn on_new_bar(&mut self, time: i64, open: f64, high: f64, low: f64, close: f64) {
+        let bar = Bar::new(time, open, high, low, close);
+        self.analyzer.on_new_bar(&bar);
+    }
+
+    fn fractals<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        fractal_columns(py, self.analyzer.fractals())
+    }
+
+    // 笔的端点
+    fn pen_points<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        fractal_columns(py, self.analyzer.pen_points())
+    }
+
+    fn pens<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        line_columns(py, self.analyzer.pens())
+    }
+
+    fn segments<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        line_columns(py, self.analyzer.segments())
+    }
+
+    fn pivots<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        pivot_columns(py, &self.analyzer)
+    }
+}
+
+// 批量计算，返回{"fractals": {...}, "pen_points": {...}, "pens": {...}, "segments": {...}, "pivots": {...}}
+#[pyfunction]
+fn analyze<'py>(
+    py: Python<'py>,
+    time: PyReadonlyArray1<'py, i64>,
+    open: PyReadonlyArray1<'py, f64>,
+    high: PyReadonlyArray1<'py, f64>,
+    low: PyReadonlyArray1<'py, f64>,
+    close: PyReadonlyArray1<'py, f64>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let (time, open, high, low, close) = (
+        time.as_array(),
+        open.as_array(),
+        high.as_array(),
+        low.as_array(),
+        close.as_array(),
+    );
+    let len = time.len();
+    if [open.len(), high.len(), low.len(), close.len()]
+        .iter()
+        .any(|l| *l != len)
+    {
+        return Err(PyValueError::new_err("arrays must have the same length"));
+    }
+
+    let mut analyzer = Analyzer::new();
+    for i in 0..len {
+        let bar = Bar::new(time[i], open[i], high[i], low[i], close[i]);
+        analyzer.on_new_bar(&bar);
+    }
+
+    let result = PyDict::new(py);
+    result.set_item("fractals", fractal_columns(py, analyzer.fractals())?)?;
+    result.set_item("pen_points", fractal_columns(py, analyzer.pen_points())?)?;
+    result.set_item("pens", line_columns(py, analyzer.pens())?)?;
+    result.set_item("segments", line_columns(py, analyzer.segments())?)?;
+    result.set_item("pivots", pivot_columns(py, &analyzer)?)?;
+    Ok(result)
+}
+
+#[pymodule]
+fn rzen(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyAnalyzer>()?;
+    m.add_function(wrap_pyfunction!(analyze, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use crate::fractal::{Fractal, FractalType};
+use crate::line::Line;
+use crate::pen_detector::PenEvent;
+use crate::sd2::{SegmentDetector, SegmentDirection, SegmentEvent};
+
+// 级别的递归
+// 本级别确认的线段作为高一级别的笔，用同样的特征序列算法(sd2)得到高一级别的线段
+// 从1分钟数据开始就可以得到任意多个级别，不需要合成高周期K线
+
+// 把本级别的线段事件转换成高一级别的笔事件
+// 已经确认的线段是确认的笔，正在延伸的线段是最后一笔，随线段延伸而延伸
+// 正在延伸的线段可能被放弃，只在副本上作为最后一笔
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SegmentToPen {
+    // 最后一个线段的起点或者已经确认的终点
+    last: Option<Fractal>,
+    // 已经发出的最后一笔的终点
+    end: Option<Fractal>,
+}
+
+impl SegmentToPen {
+    fn new() -> Self {
+        Self {
+            last: None,
+            end: None,
+        }
+    }
+
+    // 把最后一笔延伸到f，同类分型只在创新高(低)时延伸，否则新增一笔
+    fn extend_to(&mut self, f: &Fractal, events: &mut Vec<PenEvent>) {
+        let end = self.end.as_ref().unwrap();
+        if end.time() == f.time() {
+            return;
+        }
+        if end.is_same_type(f) {
+            let is_beyond = match f.fractal_type() {
+                FractalType::Top => f.price() > end.price(),
+                FractalType::Bottom => f.price() < end.price(),
+            };
+            if !is_beyond {
+                return;
+            }
+            events.push(PenEvent::UpdateTo(f.clone()));
+        } else {
+            events.push(PenEvent::New(f.clone()));
+        }
+        self.end = Some(f.clone());
+    }
+
+    fn on_new_point(&mut self, from: &Fractal, to: &Fractal, events: &mut Vec<PenEvent>) {
+        if self.end.is_none() {
+            events.push(PenEvent::First(from.clone(), to.clone()));
+            self.end = Some(to.clone());
+            return;
+        }
+        // 线段重新开始寻找时，新线段的起点与上一线段的终点不同
+        // 先把最后一笔延伸到新线段的起点，保证顶底交替
+        let is_continuous = match self.last.as_ref() {
+            Some(last) => last.time() == from.time(),
+            None => false,
+        };
+        if !is_continuous {
+            self.extend_to(from, events);
+        }
+        self.extend_to(to, events);
+    }
+
+    fn on_segment_event(&mut self, segment_event: &SegmentEvent) -> Vec<PenEvent> {
+        let mut events = Vec::new();
+        match segment_event {
+            SegmentEvent::New(a, b) => {
+                self.on_new_point(a, b, &mut events);
+                self.last = Some(b.clone());
+            }
+            SegmentEvent::New2(a, b, c) => {
+                self.on_new_point(a, b, &mut events);
+                self.last = Some(b.clone());
+                self.on_new_point(b, c, &mut events);
+                self.last = Some(c.clone());
+            }
+        }
+        events
+    }
+
+    // 正在延伸的线段作为最后一笔
+    fn on_segment_state(&mut self, from: &Fractal, to: &Fractal) -> Vec<PenEvent> {
+        let mut events = Vec::new();
+        self.on_new_point(from, to, &mut events);
+        events
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Level {
+    pens: SegmentToPen,
+    detector: SegmentDetector,
+    // 本级别已经确认的线段
+    segments: Vec<Line>,
+}
+
+impl Level {
+    fn new() -> Self {
+        Self {
+            pens: SegmentToPen::new(),
+            detector: SegmentDetector::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    fn on_pen_events(&mut self, pen_events: Vec<PenEvent>, events: &mut Vec<SegmentEvent>) {
+        for pen_event in pen_events {
+            if let Some(event) = self.detector.on_pen_event(pen_event) {
+                match &event {
+                    SegmentEvent::New(a, b) => self.segments.push(Line::new_from_pen(a, b)),
+                    SegmentEvent::New2(a, b, c) => {
+                        self.segments.push(Line::new_from_pen(a, b));
+                        self.segments.push(Line::new_from_pen(b, c));
+                    }
+                }
+                events.push(event);
+            }
+        }
+    }
+
+    // 返回本级别新确认的线段事件，以及本级别正在延伸的线段
+    // 低级别正在延伸的线段只用来计算本级别正在延伸的线段，不改变已经确认的状态
+    // 低级别放弃正在延伸的线段(lower_current为None)时，本级别回到只有确认线段的状态
+    fn on_lower(
+        &mut self,
+        lower_events: &[SegmentEvent],
+        lower_current: Option<&(Fractal, Fractal)>,
+    ) -> (Vec<SegmentEvent>, Option<(Fractal, Fractal)>) {
+        let mut events = Vec::new();
+        for segment_event in lower_events {
+            let pen_events = self.pens.on_segment_event(segment_event);
+            self.on_pen_events(pen_events, &mut events);
+        }
+        let current = match lower_current {
+            Some((from, to)) => {
+                let mut pens = self.pens.clone();
+                let mut detector = self.detector.clone();
+                for pen_event in pens.on_segment_state(from, to) {
+                    detector.on_pen_event(pen_event);
+                }
+                detector
+                    .current_segment()
+                    .map(|(from, to)| (from.clone(), to.clone()))
+            }
+            None => self
+                .detector
+                .current_segment()
+                .map(|(from, to)| (from.clone(), to.clone())),
+        };
+        (events, current)
+    }
+}
+
+// levels[0]为以线段为笔构成的线段，levels[1]以levels[0]的线段为笔，依此类推
+// 低级别出现线段(包括正在延伸的线段)之后才创建高一级别
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecursiveDetector {
+    levels: Vec<Level>,
+}
+
+impl RecursiveDetector {
+    pub fn new() -> Self {
+        Self { levels: Vec::new() }
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn segments(&self, level: usize) -> &Vec<Line> {
+        &self.levels[level].segments
+    }
+
+    pub fn direction(&self, level: usize) -> Option<SegmentDirection> {
+        self.levels[level].detector.direction()
+    }
+
+    // 每次线段检测之后调用，segment_event为新确认的线段，current为SegmentDetector::current_segment
+    // 返回各级别新确认的线段事件，(级别, 事件)
+    pub fn on_segment_event(
+        &mut self,
+        segment_event: Option<&SegmentEvent>,
+        current: Option<(&Fractal, &Fractal)>,
+    ) -> Vec<(usize, SegmentEvent)> {
+        let mut result = Vec::new();
+        let mut events: Vec<SegmentEvent> = segment_event.cloned().into_iter().collect();
+        let mut current = current.map(|(from, to)| (from.clone(), to.clone()));
+        let mut level = 0;
+        while !events.is_empty() || current.is_some() {
+            if level == self.levels.len() {
+                self.levels.push(Level::new());
+            }
+            let (higher, higher_current) = self.levels[level].on_lower(&events, current.as_ref());
+            result.extend(higher.iter().map(|e| (level, e.clone())));
+            events = higher;
+            current = higher_current;
+            level += 1;
+        }
+        result
+    }
+}
+
+impl Default for RecursiveDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::*;
+
+    #[test]
+    fn test_recursive_segment() {
+        // 把线段当作笔输入，与sd2的情况一相同
+        let points = make_fractals(&[1.0, 3.0, 2.0, 4.0, 3.0, 6.0, 3.5, 5.5, 2.5, 3.0]);
+        let mut rd = RecursiveDetector::new();
+        let mut events = Vec::new();
+        for w in points.windows(2) {
+            let event = SegmentEvent::New(w[0].clone(), w[1].clone());
+            events.extend(rd.on_segment_event(Some(&event), None));
+        }
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            (0, SegmentEvent::New(start, end)) => {
+                assert_eq!(start.price(), 1.0);
+                assert_eq!(end.price(), 6.0);
+            }
+            _ => panic!("expect level 0 SegmentEvent::New"),
+        }
+        assert_eq!(rd.segments(0).len(), 1);
+        assert_eq!(rd.level_count(), 2);
+    }
+
+    #[test]
+    fn test_discontinuous_segment() {
+        let points = make_fractals(&[1.0, 3.0, 2.0, 4.0, 3.0]);
+        let new = |a: usize, b: usize| SegmentEvent::New(points[a].clone(), points[b].clone());
+
+        // 新线段从底开始，与上一线段终点(顶)不同类，补上一笔
+        let mut pens = SegmentToPen::new();
+        let mut events = pens.on_segment_event(&new(0, 1));
+        events.extend(pens.on_segment_event(&new(2, 3)));
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[1], PenEvent::New(f) if f.price() == 2.0));
+        assert!(matches!(&events[2], PenEvent::New(f) if f.price() == 4.0));
+
+        // 新线段从顶开始，与上一线段终点同类，延伸上一笔
+        let mut pens = SegmentToPen::new();
+        let mut events = pens.on_segment_event(&new(0, 1));
+        events.extend(pens.on_segment_event(&new(3, 4)));
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[1], PenEvent::UpdateTo(f) if f.price() == 4.0));
+        assert!(matches!(&events[2], PenEvent::New(f) if f.price() == 3.0));
+
+        // 新线段的起点没有创新高，不延伸上一笔
+        let mut pens = SegmentToPen::new();
+        let mut events = pens.on_segment_event(&new(2, 3));
+        events.extend(pens.on_segment_event(&new(1, 2)));
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[1], PenEvent::New(f) if f.price() == 2.0));
+    }
+
+    #[test]
+    fn test_forming_segment() {
+        let points = make_fractals(&[1.0, 3.0, 2.0, 4.0, 3.0]);
+        let mut pens = SegmentToPen::new();
+
+        // 正在延伸的线段在副本上作为最后一笔
+        let events = pens.clone().on_segment_state(&points[0], &points[1]);
+        assert!(matches!(&events[..], [PenEvent::First(_, f)] if f.price() == 3.0));
+
+        // 线段确认之后，下一个正在延伸的线段新增一笔
+        let event = SegmentEvent::New(points[0].clone(), points[3].clone());
+        assert_eq!(pens.on_segment_event(&event).len(), 1);
+        let events = pens.clone().on_segment_state(&points[3], &points[4]);
+        assert!(matches!(&events[..], [PenEvent::New(f)] if f.price() == 3.0));
+    }
+
+    #[test]
+    fn test_abandoned_segment() {
+        let points = make_fractals(&[1.0, 3.0, 2.0, 4.0, 3.0, 6.0, 3.5]);
+        let new = |a: usize, b: usize| SegmentEvent::New(points[a].clone(), points[b].clone());
+        let prices = |segment: Option<(Fractal, Fractal)>| {
+            segment.map(|(from, to)| (from.price(), to.price()))
+        };
+        let mut rd = RecursiveDetector::new();
+        for i in 0..5 {
+            rd.on_segment_event(Some(&new(i, i + 1)), None);
+        }
+
+        // 正在延伸的线段确认了前一笔，高一级别的线段延伸到6.0
+        let forming = (points[5].clone(), points[6].clone());
+        rd.on_segment_event(None, Some((&forming.0, &forming.1)));
+        let (_, current) = rd.levels[0].on_lower(&[], Some(&forming));
+        assert_eq!(prices(current), Some((1.0, 6.0)));
+
+        // 正在延伸的线段被放弃，高一级别回到只有确认线段的状态
+        rd.on_segment_event(None, None);
+        let (_, current) = rd.levels[0].on_lower(&[], None);
+        assert_eq!(prices(current), Some((1.0, 4.0)));
+        assert!(rd.segments(0).is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+use chrono::{DateTime, Datelike, NaiveDate};
+
+use crate::bar::Bar;
+use crate::time::{Time, DAY_UNIT, HOUR_UNIT, MINUTE_UNIT};
+
+// K线周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    // N分钟，按交易时间计算，午休不计入
+    Minute(i64),
+    Day,
+    Week,
+}
+
+// 时区
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    // 固定的UTC偏移，毫秒
+    Fixed(Time),
+    // 纽约时间，考虑夏令时
+    NewYork,
+}
+
+// 美国夏令时：三月第二个星期日2:00到十一月第一个星期日2:00
+fn nth_sunday(year: i32, month: u32, nth: u32, utc_hour: u32) -> Time {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let days = (7 - first.weekday().num_days_from_sunday()) % 7 + (nth - 1) * 7;
+    let date = first + chrono::Duration::days(days as i64);
+    date.and_hms_opt(utc_hour, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp_millis()
+}
+
+impl Zone {
+    pub fn offset(&self, time: Time) -> Time {
+        match self {
+            Zone::Fixed(offset) => *offset,
+            Zone::NewYork => {
+                let year = DateTime::from_timestamp_millis(time).unwrap().year();
+                // 2:00 EST = 7:00 UTC，2:00 EDT = 6:00 UTC
+                let start = nth_sunday(year, 3, 2, 7);
+                let end = nth_sunday(year, 11, 1, 6);
+                if time >= start && time < end {
+                    -4 * HOUR_UNIT
+                } else {
+                    -5 * HOUR_UNIT
+                }
+            }
+        }
+    }
+}
+
+// 交易时段
+// 交易日从第一个时段开始，时段为本地时间当天的毫秒数，结束时间可以超过24小时表示跨越午夜
+#[derive(Debug, Clone)]
+pub struct Session {
+    zone: Zone,
+    // 交易日开始的本地时间
+    open: Time,
+    // 相对交易日开始的时段，[start, end)
+    ranges: Vec<(Time, Time)>,
+}
+
+impl Session {
+    pub fn new(zone: Zone, ranges: &[(Time, Time)]) -> Self {
+        debug_assert!(!ranges.is_empty());
+        let open = ranges[0].0;
+        let ranges: Vec<(Time, Time)> = ranges.iter().map(|(s, e)| (s - open, e - open)).collect();
+        debug_assert!(ranges.windows(2).all(|w| w[0].1 <= w[1].0));
+        debug_assert!(ranges.last().unwrap().1 <= DAY_UNIT);
+        Self { zone, open, ranges }
+    }
+
+    // UTC零点开始的全天交易
+    pub fn utc() -> Self {
+        Self::new(Zone::Fixed(0), &[(0, DAY_UNIT)])
+    }
+
+    // 外汇，纽约时间17:00换日
+    pub fn fx() -> Self {
+        let open = 17 * HOUR_UNIT;
+        Self::new(Zone::NewYork, &[(open, open + DAY_UNIT)])
+    }
+
+    // A股，北京时间9:30-11:30，13:00-15:00
+    pub fn a_share() -> Self {
+        let time = |h: i64, m: i64| h * HOUR_UNIT + m * MINUTE_UNIT;
+        Self::new(
+            Zone::Fixed(8 * HOUR_UNIT),
+            &[(time(9, 30), time(11, 30)), (time(13, 0), time(15, 0))],
+        )
+    }
+
+    // 每个交易日的交易时间
+    fn length(&self) -> Time {
+        self.ranges.iter().map(|(s, e)| e - s).sum()
+    }
+
+    // 返回(交易日序号, 交易日内已经过的交易时间)，不在交易时段内返回None
+    fn locate(&self, time: Time) -> Option<(i64, Time)> {
+        let local = time + self.zone.offset(time) - self.open;
+        let day = local.div_euclid(DAY_UNIT);
+        let tod = local.rem_euclid(DAY_UNIT);
+        let mut elapsed = 0;
+        for (start, end) in &self.ranges {
+            if tod >= *start && tod < *end {
+                return Some((day, elapsed + tod - start));
+            }
+            elapsed += end - start;
+        }
+        None
+    }
+
+    // 交易日内已经过的交易时间对应的UTC时间
+    fn time_at(&self, day: i64, elapsed: Time, reference: Time) -> Time {
+        let mut remain = elapsed;
+        let mut tod = 0;
+        for (start, end) in &self.ranges {
+            if remain < end - start {
+                tod = start + remain;
+                break;
+            }
+            remain -= end - start;
+        }
+        self.open + day * DAY_UNIT + tod - self.zone.offset(reference)
+    }
+
+    // 交易日的日期，以交易日结束时的本地日期为准，返回EPOCH开始的天数
+    fn date(&self, day: i64) -> i64 {
+        let end = self.open + day * DAY_UNIT + self.ranges.last().unwrap().1 - 1;
+        end.div_euclid(DAY_UNIT)
+    }
+}
+
+// 星期一为0
+fn weekday(date: i64) -> i64 {
+    // 1970/1/1是星期四
+    (date + 3).rem_euclid(7)
+}
+
+#[derive(Debug)]
+pub struct Resampler {
+    // 输入K线的周期
+    source: Time,
+    period: Period,
+    session: Session,
+    key: i64,
+    // 正在形成的K线
+    bar: Option<Bar>,
+}
+
+impl Resampler {
+    pub fn new(source: Time, period: Period, session: Session) -> Self {
+        Self {
+            source,
+            period,
+            session,
+            key: 0,
+            bar: None,
+        }
+    }
+
+    pub fn period(&self) -> Period {
+        self.period
+    }
+
+    // 当前尚未完成的K线
+    pub fn partial(&self) -> Option<&Bar> {
+        self.bar.as_ref()
+    }
+
+    // 返回(K线序号, K线开始时间, 输入K线是否为最后一根)
+    fn bucket(&self, time: Time) -> Option<(i64, Time, bool)> {
+        let (day, elapsed) = self.session.locate(time)?;
+        let length = self.session.length();
+        let day_end = elapsed + self.source >= length;
+        let result = match self.period {
+            Period::Minute(n) => {
+                let period = n * MINUTE_UNIT;
+                let index = elapsed / period;
+                let start = self.session.time_at(day, index * period, time);
+                let is_last = elapsed + self.source >= ((index + 1) * period).min(length);
+                // 每天的K线数量不超过一天的分钟数
+                (day * DAY_UNIT / MINUTE_UNIT + index, start, is_last)
+            }
+            Period::Day => (day, self.session.time_at(day, 0, time), day_end),
+            Period::Week => {
+                let date = self.session.date(day);
+                let is_last = day_end && weekday(date) == 4;
+                let week = (date + 3).div_euclid(7);
+                (week, self.session.time_at(day, 0, time), is_last)
+            }
+        };
+        Some(result)
+    }
+
+    // 返回已经完成的K线，不在交易时段内的K线被忽略
+    pub fn on_new_bar(&mut self, bar: &Bar) -> Vec<Bar> {
+        let mut completed = Vec::new();
+        let (key, start, is_last) = match self.bucket(bar.time) {
+            Some(bucket) => bucket,
+            None => return completed,
+        };
+
+        match self.bar.as_mut() {
+            Some(current) if self.key == key => {
+                current.high = current.high.max(bar.high);
+                current.low = current.low.min(bar.low);
+                current.close = bar.close;
+                current.volume += bar.volume;
+            }
+            _ => {
+                // 新的K线开始，之前的K线即使没有到结束时间也已经完成(例如节假日)
+                completed.extend(self.bar.take());
+                self.key = key;
+                let new = Bar::new(start, bar.open, bar.high, bar.low, bar.close);
+                self.bar = Some(new.with_volume(bar.volume));
+            }
+        }
+
+        if is_last {
+            completed.extend(self.bar.take());
+        }
+        completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(rs: &mut Resampler, from: Time, count: i64) -> Vec<Bar> {
+        let mut bars = Vec::new();
+        for i in 0..count {
+            let price = i as f64;
+            let bar = Bar::new(
+                from + i * MINUTE_UNIT,
+                price,
+                price + 1.0,
+                price - 1.0,
+                price + 0.5,
+            )
+            .with_volume(1.0);
+            bars.extend(rs.on_new_bar(&bar));
+        }
+        bars
+    }
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> Time {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn test_minute() {
+        let mut rs = Resampler::new(MINUTE_UNIT, Period::Minute(5), Session::utc());
+        let bars = feed(&mut rs, 0, 12);
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].time, 0);
+        assert_eq!((bars[0].open, bars[0].high, bars[0].low), (0.0, 5.0, -1.0));
+        assert_eq!(bars[0].close, 4.5);
+        assert_eq!(bars[0].volume, 5.0);
+        assert_eq!(bars[1].time, 5 * MINUTE_UNIT);
+        let partial = rs.partial().unwrap();
+        assert_eq!(partial.time, 10 * MINUTE_UNIT);
+        assert_eq!(partial.close, 11.5);
+    }
+
+    #[test]
+    fn test_a_share() {
+        // 北京时间9:30开盘，UTC 1:30
+        let mut rs = Resampler::new(MINUTE_UNIT, Period::Minute(60), Session::a_share());
+        let mut bars = feed(&mut rs, utc(2021, 3, 1, 1, 30), 120);
+        // 午休时间的K线被忽略
+        bars.extend(feed(&mut rs, utc(2021, 3, 1, 3, 30), 90));
+        bars.extend(feed(&mut rs, utc(2021, 3, 1, 5, 0), 120));
+        let times: Vec<Time> = bars.iter().map(|b| b.time).collect();
+        assert_eq!(
+            times,
+            vec![
+                utc(2021, 3, 1, 1, 30),
+                utc(2021, 3, 1, 2, 30),
+                utc(2021, 3, 1, 5, 0),
+                utc(2021, 3, 1, 6, 0)
+            ]
+        );
+        assert!(rs.partial().is_none());
+
+        let mut rs = Resampler::new(MINUTE_UNIT, Period::Day, Session::a_share());
+        let mut bars = feed(&mut rs, utc(2021, 3, 1, 1, 30), 120);
+        assert!(bars.is_empty());
+        bars.extend(feed(&mut rs, utc(2021, 3, 1, 5, 0), 120));
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].time, utc(2021, 3, 1, 1, 30));
+    }
+
+    #[test]
+    fn test_fx_day() {
+        // 冬令时纽约17:00为UTC 22:00
+        let mut rs = Resampler::new(MINUTE_UNIT, Period::Day, Session::fx());
+        let bars = feed(&mut rs, utc(2021, 1, 5, 21, 58), 4);
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].time, utc(2021, 1, 4, 22, 0));
+        assert_eq!(rs.partial().unwrap().time, utc(2021, 1, 5, 22, 0));
+
+        // 夏令时纽约17:00为UTC 21:00
+        let mut rs = Resampler::new(MINUTE_UNIT, Period::Day, Session::fx());
+        let bars = feed(&mut rs, utc(2021, 7, 6, 20, 58), 4);
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].time, utc(2021, 7, 5, 21, 0));
+    }
+
+    #[test]
+    fn test_fx_week() {
+        // 2021/1/8是星期五，纽约17:00收盘
+        let mut rs = Resampler::new(MINUTE_UNIT, Period::Week, Session::fx());
+        let bars = feed(&mut rs, utc(2021, 1, 3, 22, 0), 2);
+        assert!(bars.is_empty());
+        let bars = feed(&mut rs, utc(2021, 1, 8, 21, 58), 2);
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].time, utc(2021, 1, 3, 22, 0));
+    }
+}
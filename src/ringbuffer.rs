@@ -57,6 +57,44 @@ impl<T> RingBuffer<T> {
     }
 }
 
+// 容量就是ringbuffer的大小，clone时需要保留
+impl<T: Clone> Clone for RingBuffer<T> {
+    fn clone(&self) -> Self {
+        let mut queue = VecDeque::with_capacity(self.queue.capacity());
+        queue.extend(self.queue.iter().cloned());
+        Self { queue }
+    }
+}
+
+// 序列化时同样需要保留容量
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RingBufferData<T> {
+    capacity: usize,
+    items: Vec<T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for RingBuffer<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = RingBufferData {
+            capacity: self.queue.capacity(),
+            items: self.queue.iter().collect(),
+        };
+        data.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for RingBuffer<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = RingBufferData::<T>::deserialize(deserializer)?;
+        let mut queue = VecDeque::with_capacity(data.capacity);
+        queue.extend(data.items);
+        Ok(Self { queue })
+    }
+}
+
 #[test]
 fn test_ringbuffer() {
     let mut w = RingBuffer::<i32>::new(3);
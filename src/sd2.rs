@@ -139,27 +139,41 @@ pub enum TerminationReson {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SegmentEvent {
     New(Fractal, Fractal),
     New2(Fractal, Fractal, Fractal),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SegmentDirection {
     Up,
     Down,
 }
 
+impl SegmentDirection {
+    pub fn flip(&self) -> Self {
+        match self {
+            SegmentDirection::Up => SegmentDirection::Down,
+            SegmentDirection::Down => SegmentDirection::Up,
+        }
+    }
+}
+
 pub type FractalVecIndex = usize;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SegmentDetector {
+    // 当前线段起点开始的所有已确认笔端点，fractals[0]是当前线段的起点
     fractals: VecDeque<Fractal>,
     direction: Option<SegmentDirection>,
 
     // 假设的线段终结点
     current: FractalVecIndex,
     // 对应假设终结点的前高(低)点，用于特征分型第一元素的计算
+    // 即标准化后第一元素的起点，prev == current 表示假设终结点前没有特征序列
     prev: FractalVecIndex,
 
     // 对应线段终结第一种情况，保存3个分型判断即可
@@ -181,6 +195,16 @@ impl SegmentDetector {
         }
     }
 
+    pub fn direction(&self) -> Option<SegmentDirection> {
+        self.direction
+    }
+
+    // 正在延伸中的线段，(起点, 假设的终结点)，还在寻找第一个线段时返回None
+    pub fn current_segment(&self) -> Option<(&Fractal, &Fractal)> {
+        self.direction?;
+        Some((self.fractals.front()?, self.fractals.get(self.current)?))
+    }
+
     // 判断第一个线段的时候，条件约束较严格
     fn is_first_segment(
         p1: &Fractal,
@@ -209,50 +233,85 @@ impl SegmentDetector {
         direction
     }
 
-    // 特征序列进行标准化
+    // 以index为起点的笔
+    fn line(&self, index: FractalVecIndex) -> Line {
+        Line::new_from_pen(&self.fractals[index], &self.fractals[index + 1])
+    }
+
+    // 特征序列进行标准化，第一元素只处理前包含
     // [start, end) end不包含在里面
-    fn merge_seq(&self, start: usize, end: usize, dir: MergeDirection) -> Line {
+    // 返回标准化后最后一个元素的起点以及该元素
+    fn merge_seq(
+        &self,
+        start: usize,
+        end: usize,
+        dir: MergeDirection,
+    ) -> Option<(FractalVecIndex, Line)> {
+        let mut result: Option<(FractalVecIndex, Line)> = None;
         let mut from_index = start;
-        let from = self.get(from_index as isize).unwrap();
-        let to = self.get((from_index + 1) as isize).unwrap();
-        let mut seq = Line::new(
-            //            from_index,
-            from.time(),
-            from.price(),
-            //          from_index + 1,
-            to.time(),
-            to.price(),
-        );
-        while from_index + 2 < end {
-            from_index += 2;
-            let new_from = self.get(from_index as isize).unwrap();
-            let new_to = self.get((from_index + 1) as isize).unwrap();
-            let new_seq = Line::new(
-                //from_index,
-                new_from.time(),
-                new_from.price(),
-                //from_index + 1,
-                new_to.time(),
-                new_to.price(),
-            );
-            let is_merged = seq.merge(&new_seq, dir);
+        while from_index + 1 < end {
+            let seq = self.line(from_index);
+            let is_merged = match result.as_mut() {
+                Some((_, last)) => last.merge_front(&seq, dir),
+                None => false,
+            };
             if !is_merged {
-                break;
+                result = Some((from_index, seq));
             }
+            from_index += 2;
         }
-        seq
+        result
     }
 
-    fn reset_state(&mut self, start_point: usize, prev: usize, current: usize) {
-        debug_assert!(current - prev >= 2);
-        debug_assert!(start_point < self.fractals.len());
-        self.fractals.drain(..start_point);
+    // 设定假设终结点，并根据假设点重新计算第一元素
+    fn reset_state(&mut self, current: FractalVecIndex) {
+        debug_assert!(current < self.fractals.len());
         self.current = current;
-        self.prev = prev;
         self.window1.clear();
         self.window2.clear();
-        let seq = self.merge_seq(prev, current, self.merge_direction());
-        self.window1.push(seq);
+        // 特征序列是与线段方向相反的笔，从线段的第二个端点开始
+        match self.merge_seq(1, current, self.merge_direction()) {
+            Some((prev, seq)) => {
+                self.prev = prev;
+                self.window1.push(seq);
+            }
+            None => {
+                self.prev = current;
+            }
+        }
+    }
+
+    // 线段终结后，以fractals[0]为新线段起点，重新计算假设终结点及特征序列
+    fn rebuild(&mut self) {
+        let current = self.find_extreme();
+        self.reset_state(current);
+        let end = self.fractals.len() - 1;
+        for index in current..end {
+            self.add_pen(index);
+        }
+    }
+
+    // 新线段起点之后的最高(低)点，与起点类型相反的端点位于奇数位置
+    fn find_extreme(&self) -> FractalVecIndex {
+        if self.fractals.len() < 2 {
+            return 0;
+        }
+        let direction = self.direction.unwrap();
+        let mut extreme = 1;
+        let mut index = 3;
+        while index < self.fractals.len() {
+            let price = self.fractals[index].price();
+            let extreme_price = self.fractals[extreme].price();
+            let is_extreme = match direction {
+                SegmentDirection::Up => price > extreme_price,
+                SegmentDirection::Down => price < extreme_price,
+            };
+            if is_extreme {
+                extreme = index;
+            }
+            index += 2;
+        }
+        extreme
     }
 
     fn find_first_segment(&mut self) -> Option<SegmentEvent> {
@@ -270,52 +329,47 @@ impl SegmentDetector {
         self.direction = SegmentDetector::is_first_segment(p1, p2, p3, p4);
 
         if self.direction.is_some() {
+            // 第一个线段此时只是成立，还可能延伸，等到线段终结时才发出SegmentEvent
             let len = self.fractals.len();
-            self.reset_state(self.fractals.len() - 4, len - 3, len - 1);
-            let start = self.get(-4).unwrap().clone();
-            let end = self.get(-1).unwrap().clone();
-            Some(SegmentEvent::New(start, end))
+            self.fractals.drain(..len - 4);
+            self.reset_state(3);
+        }
+        None
+    }
+
+    // index开始的笔，与线段方向相反的放入window1，相同的放入window2
+    fn add_pen(&mut self, index: FractalVecIndex) {
+        debug_assert!(index >= self.current);
+        let segment_dir = self.direction.unwrap();
+        let seq = self.line(index);
+        if index % 2 == self.current % 2 {
+            self.add_seq_on_window1(seq, SegmentDetector::get_merge_direction(segment_dir));
         } else {
-            //self.fractals.pop_front();
-            None
+            self.add_seq_on_window2(seq, SegmentDetector::get_flip_merge_direction(segment_dir));
         }
     }
 
-    fn add_seq_on_window1(&mut self, dir: MergeDirection) {
-        debug_assert!(self.window1.len() > 0);
-        let length = self.window1.len();
-        let last = self.get(-1).unwrap();
-        let prev = self.get(-2).unwrap();
-        let seq = Line::new(
-            //length - 2,
-            prev.time(),
-            prev.price(),
-            //length - 1,
-            last.time(),
-            last.price(),
-        );
-        let length = self.window1.len();
-        if length > 1 {
+    fn add_seq_on_window1(&mut self, seq: Line, dir: MergeDirection) {
+        // 没有第一元素或者分型已经形成，不再处理
+        if self.prev == self.current || self.window1.len() >= 3 {
+            return;
+        }
+
+        if self.window1.len() == 1 {
+            // 第二元素从假设终结点开始，与第一元素之间不做包含处理
+            self.window1.push(seq);
+        } else {
+            // 第二元素只处理前包含，第三元素不处理包含
             let s = self.window1.get_mut(-1).unwrap();
-            let is_merged = s.merge(&seq, dir);
+            let is_merged = s.merge_front(&seq, dir);
             if !is_merged {
                 self.window1.push(seq);
             }
         }
     }
 
-    fn add_seg_on_window2(&mut self, dir: MergeDirection) {
-        let length = self.window1.len();
-        let last = self.get(-1).unwrap();
-        let prev = self.get(-2).unwrap();
-        let seq = Line::new(
-            //length - 2,
-            prev.time(),
-            prev.price(),
-            //length - 1,
-            last.time(),
-            last.price(),
-        );
+    fn add_seq_on_window2(&mut self, seq: Line, dir: MergeDirection) {
+        // 第二特征序列前包含、后包含都要处理
         let length = self.window2.len();
         if length > 0 {
             let s = self.window2.get_mut(-1).unwrap();
@@ -328,39 +382,95 @@ impl SegmentDetector {
         }
     }
 
-    fn on_new_pen(&mut self) -> Option<SegmentEvent> {
-        // 每当新的一笔确认，在假设点前后，填充情况一及情况二的序列(window1, window2)
-        //debug_assert!(self.window1.len() == 1);
-        debug_assert!(self.fractals.len() > self.current);
-        debug_assert!(self.direction.is_some());
+    // 检查线段是否终结
+    fn check_termination(&self) -> Option<TerminationReson> {
+        // 假设终结点之后不会再有新高新低，所以第三元素出现，第一特征序列的分型就已经成立
+        if self.window1.len() < 3 {
+            return None;
+        }
+
+        let direction = self.direction.unwrap();
+        let e1 = self.window1.get(0).unwrap();
+        let e2 = self.window1.get(1).unwrap();
+        let has_gap = match direction {
+            SegmentDirection::Up => e1.high() < e2.low(),
+            SegmentDirection::Down => e1.low() > e2.high(),
+        };
+
+        if !has_gap {
+            return Some(TerminationReson::CASE1);
+        }
 
-        // 具体过程如下：
+        // 第一特征序列分型有缺口，需要第二特征序列出现分型才能确认
+        if self.window2.len() < 3 {
+            return None;
+        }
+
+        let s1 = self.window2.get(-3).unwrap();
+        let s2 = self.window2.get(-2).unwrap();
+        let s3 = self.window2.get(-1).unwrap();
+        let (is_fractal, has_gap) = match direction {
+            SegmentDirection::Up => (Line::is_bottom_fractal(s1, s2, s3), s1.low() > s2.high()),
+            SegmentDirection::Down => (Line::is_top_fractal(s1, s2, s3), s1.high() < s2.low()),
+        };
+
+        match (is_fractal, has_gap) {
+            (true, false) => Some(TerminationReson::CASE21),
+            (true, true) => Some(TerminationReson::CASE22),
+            (false, _) => None,
+        }
+    }
+
+    // 线段终结处理，发出SegmentEvent并以新线段起点重新计算状态
+    fn flip(&mut self, reason: TerminationReson) -> SegmentEvent {
+        let direction = self.direction.unwrap();
+        let start = self.fractals[0].clone();
+        let end = self.fractals[self.current].clone();
+        match reason {
+            TerminationReson::CASE1 | TerminationReson::CASE22 => {
+                // 前线段终结，新线段开始，方向反转
+                self.fractals.drain(..self.current);
+                self.direction = Some(direction.flip());
+                self.rebuild();
+                SegmentEvent::New(start, end)
+            }
+
+            TerminationReson::CASE21 => {
+                // 前两个线段终结，新线段开始，方向不变
+                let s2 = self.window2.get(-2).unwrap();
+                let point = match direction {
+                    SegmentDirection::Up => s2.low_point(),
+                    SegmentDirection::Down => s2.high_point(),
+                };
+                let third_index = self
+                    .fractals
+                    .iter()
+                    .skip(self.current)
+                    .position(|f| f.time() == point.time())
+                    .unwrap()
+                    + self.current;
+                let third = self.fractals[third_index].clone();
+                self.fractals.drain(..third_index);
+                self.rebuild();
+                SegmentEvent::New2(start, end, third)
+            }
+        }
+    }
+
+    fn on_new_pen(&mut self) -> Option<SegmentEvent> {
+        // 每当新的一笔确认，在假设点后，填充情况一及情况二的序列(window1, window2)
         // 与线段当前方向相反的笔合并处理后放入window1
         // 与线段当前方向相同的笔合并处理后放入window2
-        // 当window1的数量达到3，看是否是case1，如果是case1，形成顶分型，线段结束
-        // 如果不是，。。。。
-        // 当window2的数量达到3，如果是底分型，线段1结束，
-        let segment_dir = self.direction.unwrap();
-        let last = self.get(-1).unwrap();
-        let prev = self.get(-2).unwrap();
-        let is_same_direction_up =
-            segment_dir == SegmentDirection::Up && last.price() > prev.price();
-        let is_same_direction_down =
-            segment_dir == SegmentDirection::Down && last.price() < prev.price();
-        let is_same_direction = is_same_direction_up || is_same_direction_down;
-        if is_same_direction {
-            self.add_seg_on_window2(SegmentDetector::get_flip_merge_direction(segment_dir));
-        } else {
-            self.add_seq_on_window1(SegmentDetector::get_merge_direction(segment_dir));
-        }
+        // 当window1的数量达到3，第一特征序列分型成立，无缺口则线段结束(case1)
+        // 有缺口则等待window2形成分型(case21/case22)
+        debug_assert!(self.fractals.len() > self.current + 1);
+        debug_assert!(self.direction.is_some());
 
-        // TODO:这里存在一个错误，就是何时需要判断是否终结
-        // 当window1/window2的第4个分型要压入的时候
-        // 参考fractal_dector的实现
-        /*let reason = self.check_termination();
-        self.flip(reason)
-        */
-        None
+        let index = self.fractals.len() - 2;
+        self.add_pen(index);
+
+        let reason = self.check_termination()?;
+        Some(self.flip(reason))
     }
 
     fn process_normal_segment(&mut self) -> Option<SegmentEvent> {
@@ -380,16 +490,30 @@ impl SegmentDetector {
         if new_higher_or_lower {
             // 创新高或者新低，假设该点是线段终结点
             let new_assume_end_point = self.fractals.len() - 1;
-            self.reset_state(0, self.current, new_assume_end_point);
-            None
-        } else {
-            self.on_new_pen()
+            self.reset_state(new_assume_end_point);
+            return None;
+        }
+
+        if self.prev == self.current {
+            // 假设终结点之前还没有特征序列，线段不足三笔
+            // 如果此时反向突破了线段起点，该线段不成立，重新寻找第一个线段
+            let start = self.fractals[0].price();
+            let is_broken = match direction {
+                SegmentDirection::Up => last_point.price() < start,
+                SegmentDirection::Down => last_point.price() > start,
+            };
+            if is_broken {
+                self.direction = None;
+                return None;
+            }
         }
+
+        self.on_new_pen()
     }
 
     pub fn process(&mut self) -> Option<SegmentEvent> {
         // 调用本方法，所以至少需要4个分型端点
-        if self.fractals.len() < 5 {
+        if self.fractals.len() < 4 {
             return None;
         }
 
@@ -454,3 +578,90 @@ impl SegmentDetector {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::*;
+
+    fn feed(sd: &mut SegmentDetector, prices: &[f64]) -> Vec<SegmentEvent> {
+        let points = make_fractals(prices);
+        let mut events = Vec::new();
+        sd.on_pen_event(PenEvent::First(points[0].clone(), points[1].clone()));
+        for p in points.iter().skip(2) {
+            if let Some(event) = sd.on_pen_event(PenEvent::New(p.clone())) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_segment_case1() {
+        let mut sd = SegmentDetector::new();
+        let events = feed(&mut sd, &[1.0, 3.0, 2.0, 4.0, 3.0, 6.0, 3.5, 5.5, 2.5, 3.0]);
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            SegmentEvent::New(start, end) => {
+                assert_eq!(start.price(), 1.0);
+                assert_eq!(end.price(), 6.0);
+            }
+            _ => panic!("expect SegmentEvent::New"),
+        }
+        assert_eq!(sd.direction(), Some(SegmentDirection::Down));
+    }
+
+    #[test]
+    fn test_segment_case21() {
+        let mut sd = SegmentDetector::new();
+        let events = feed(
+            &mut sd,
+            &[
+                1.0, 3.0, 2.0, 4.0, 3.0, 6.0, 4.5, 5.0, 3.5, 4.6, 3.8, 4.8, 4.0,
+            ],
+        );
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            SegmentEvent::New2(start, end, third) => {
+                assert_eq!(start.price(), 1.0);
+                assert_eq!(end.price(), 6.0);
+                assert_eq!(third.price(), 3.5);
+            }
+            _ => panic!("expect SegmentEvent::New2"),
+        }
+        assert_eq!(sd.direction(), Some(SegmentDirection::Up));
+    }
+
+    #[test]
+    fn test_segment_case22() {
+        let mut sd = SegmentDetector::new();
+        let events = feed(
+            &mut sd,
+            &[
+                1.0, 3.0, 2.0, 4.0, 3.0, 6.0, 4.5, 5.0, 3.5, 4.2, 3.8, 4.8, 4.0,
+            ],
+        );
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            SegmentEvent::New(start, end) => {
+                assert_eq!(start.price(), 1.0);
+                assert_eq!(end.price(), 6.0);
+            }
+            _ => panic!("expect SegmentEvent::New"),
+        }
+        assert_eq!(sd.direction(), Some(SegmentDirection::Down));
+    }
+
+    #[test]
+    fn test_segment_extend() {
+        // 第一特征序列有缺口，第二特征序列分型出现前创新高，线段延续
+        let mut sd = SegmentDetector::new();
+        let events = feed(
+            &mut sd,
+            &[1.0, 3.0, 2.0, 4.0, 3.0, 6.0, 4.5, 5.0, 3.5, 7.0, 6.0],
+        );
+        assert!(events.is_empty());
+        assert_eq!(sd.direction(), Some(SegmentDirection::Up));
+        assert_eq!(sd.fractals[sd.current].price(), 7.0);
+    }
+}
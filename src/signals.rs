@@ -0,0 +1,455 @@
+use std::collections::VecDeque;
+
+use crate::bar::Bar;
+use crate::divergence::Divergence;
+use crate::fractal::Fractal;
+use crate::line::Line;
+use crate::pen_detector::PenEvent;
+use crate::pivot::{Pivot, PivotEvent, PivotLevel};
+use crate::sd2::SegmentEvent;
+use crate::time::Time;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+// 第一类买卖点
+// 下跌趋势最后一个中枢之后出现底背驰，背驰段的终点即为第一类买点，卖点反之
+// 背驰段确认的那根K线发出信号，time为发出信号的K线时间
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FirstPoint {
+    pub level: PivotLevel,
+    pub side: Side,
+    // 背驰段结束的分型
+    pub fractal: Fractal,
+    pub divergence: Divergence,
+    pub time: Time,
+}
+
+impl FirstPoint {
+    pub fn price(&self) -> f64 {
+        self.fractal.price()
+    }
+
+    pub fn pivot(&self) -> &Pivot {
+        &self.divergence.pivot
+    }
+}
+
+// 第二类买卖点
+// 第一类买点之后，第一次回调不创新低，回调的终点即为第二类买点，卖点反之
+// 回调的一笔(段)确认的那根K线发出信号
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SecondPoint {
+    pub level: PivotLevel,
+    pub side: Side,
+    pub first: FirstPoint,
+    // 回调结束的分型
+    pub fractal: Fractal,
+    // 回调的一笔(段)
+    pub pullback: Line,
+    // 止损价，即第一类买卖点的价格，跌破(升破)则失效
+    pub invalidation: f64,
+    // 反弹已经离开第一类买卖点对应的中枢，且回调不回到中枢，二三买卖点重合
+    pub coincide: bool,
+    pub time: Time,
+}
+
+impl SecondPoint {
+    pub fn price(&self) -> f64 {
+        self.fractal.price()
+    }
+}
+
+// 第三类买卖点
+// 向上离开中枢之后，第一次回调不回到中枢，即回调低点高于ZG，回调的终点即为第三类买点，卖点反之
+// 回调的一笔(段)确认的那根K线发出信号
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThirdPoint {
+    pub level: PivotLevel,
+    pub side: Side,
+    pub pivot: Pivot,
+    // 离开中枢的一笔(段)
+    pub breakout: Line,
+    // 回调结束的分型
+    pub fractal: Fractal,
+    // 回调的一笔(段)
+    pub pullback: Line,
+    pub time: Time,
+}
+
+impl ThirdPoint {
+    pub fn price(&self) -> f64 {
+        self.fractal.price()
+    }
+
+    pub fn zg(&self) -> f64 {
+        self.pivot.zg
+    }
+
+    pub fn zd(&self) -> f64 {
+        self.pivot.zd
+    }
+}
+
+// 买卖点数量很少，不需要为了enum的大小装箱
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Signal {
+    First(FirstPoint),
+    Second(SecondPoint),
+    Third(ThirdPoint),
+    // 第三类买卖点发出之后，信号K线结束之前价格回到中枢，time为回到中枢的K线时间
+    ThirdCancel(ThirdPoint, Time),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignalDetector {
+    level: PivotLevel,
+    // 当前中枢进入段之后的笔端点，用来找到背驰段结束的分型
+    fractals: VecDeque<Fractal>,
+    // 等待第二类买卖点的第一类买卖点，以及之后已经确认的端点
+    first: Option<(FirstPoint, Vec<Fractal>)>,
+    // 离开中枢之后回调没有回到中枢，等待回调确认
+    leave: Option<(Pivot, Line)>,
+    // 已经发出的第三类买卖点，信号K线结束之前价格回到中枢则取消
+    third: Option<ThirdPoint>,
+}
+
+impl SignalDetector {
+    pub fn new(level: PivotLevel) -> Self {
+        Self {
+            level,
+            fractals: VecDeque::new(),
+            first: None,
+            leave: None,
+            third: None,
+        }
+    }
+
+    fn find_fractal(&self, time: Time) -> Option<&Fractal> {
+        self.fractals.iter().rev().find(|f| f.time() == time)
+    }
+
+    // 第一类买卖点之后确认了一个端点
+    fn check_second(&mut self, point: &Fractal, time: Time) -> Option<Signal> {
+        let (first, points) = self.first.as_mut()?;
+        if point.time() <= first.fractal.time() {
+            return None;
+        }
+        points.push(point.clone());
+        if points.len() < 2 {
+            return None;
+        }
+
+        // 第一次回调已经确认，不管是否创新低(高)都不再等待
+        let (first, points) = self.first.take().unwrap();
+        let (rebound, pullback) = (&points[0], &points[1]);
+        let pivot = first.pivot();
+        let (is_valid, coincide) = match first.side {
+            Side::Buy => (
+                pullback.price() > first.price(),
+                rebound.price() > pivot.zg && pullback.price() > pivot.zg,
+            ),
+            Side::Sell => (
+                pullback.price() < first.price(),
+                rebound.price() < pivot.zd && pullback.price() < pivot.zd,
+            ),
+        };
+        if !is_valid {
+            return None;
+        }
+        Some(Signal::Second(SecondPoint {
+            level: self.level,
+            side: first.side,
+            fractal: pullback.clone(),
+            pullback: Line::new_from_pen(rebound, pullback),
+            invalidation: first.price(),
+            coincide,
+            first,
+            time,
+        }))
+    }
+
+    // 中枢结束之后确认的第一个端点就是回调的终点
+    fn check_third(&mut self, point: &Fractal, time: Time) -> Option<Signal> {
+        let (_, breakout) = self.leave.as_ref()?;
+        if point.time() <= breakout.to().time() {
+            return None;
+        }
+        let (pivot, breakout) = self.leave.take().unwrap();
+        let from = self.find_fractal(breakout.to().time())?;
+        let pullback = Line::new_from_pen(from, point);
+        let side = if breakout.is_up() {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
+        let is_valid = match side {
+            Side::Buy => pullback.low() > pivot.zg,
+            Side::Sell => pullback.high() < pivot.zd,
+        };
+        if !is_valid {
+            return None;
+        }
+        let point = ThirdPoint {
+            level: self.level,
+            side,
+            pivot,
+            breakout,
+            fractal: point.clone(),
+            pullback,
+            time,
+        };
+        self.third = Some(point.clone());
+        Some(Signal::Third(point))
+    }
+
+    fn on_confirmed(&mut self, point: &Fractal, time: Time) -> Vec<Signal> {
+        let mut signals = Vec::new();
+        signals.extend(self.check_second(point, time));
+        signals.extend(self.check_third(point, time));
+        signals
+    }
+
+    // 已经发出的第三类买卖点，信号K线(发出信号之后的第一根K线)回到中枢则取消
+    // 信号K线结束之后不再检查
+    pub fn on_new_bar(&mut self, bar: &Bar) -> Option<Signal> {
+        let third = self.third.take()?;
+        let is_back = match third.side {
+            Side::Buy => bar.low <= third.zg(),
+            Side::Sell => bar.high >= third.zd(),
+        };
+        if is_back {
+            Some(Signal::ThirdCancel(third, bar.time))
+        } else {
+            None
+        }
+    }
+
+    pub fn on_pen_event(&mut self, pen_event: &PenEvent, time: Time) -> Vec<Signal> {
+        match pen_event {
+            PenEvent::First(a, b) => {
+                self.fractals.push_back(a.clone());
+                self.fractals.push_back(b.clone());
+                Vec::new()
+            }
+            PenEvent::New(c) => {
+                // 新笔出现，前一笔确认
+                let confirmed = self.fractals.back().cloned();
+                self.fractals.push_back(c.clone());
+                // 线段级别的端点由SegmentEvent确认
+                match confirmed {
+                    Some(point) if self.level == PivotLevel::Pen => self.on_confirmed(&point, time),
+                    _ => Vec::new(),
+                }
+            }
+            PenEvent::UpdateTo(c) => {
+                self.fractals.pop_back();
+                self.fractals.push_back(c.clone());
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn on_segment_event(&mut self, segment_event: &SegmentEvent, time: Time) -> Vec<Signal> {
+        debug_assert!(self.level == PivotLevel::Segment);
+        let mut signals = Vec::new();
+        match segment_event {
+            SegmentEvent::New(_, b) => signals.extend(self.on_confirmed(b, time)),
+            SegmentEvent::New2(_, b, c) => {
+                signals.extend(self.on_confirmed(b, time));
+                signals.extend(self.on_confirmed(c, time));
+            }
+        }
+        signals
+    }
+
+    pub fn on_pivot_event(&mut self, pivot_event: &PivotEvent) {
+        match pivot_event {
+            PivotEvent::New(pivot) => {
+                // 新中枢出现，之前的端点不再需要，之前的第三类买卖点也不再跟踪
+                let start = pivot.enter.from().time();
+                while self.fractals.len() > 1 && self.fractals[0].time() < start {
+                    self.fractals.pop_front();
+                }
+                self.third = None;
+            }
+            // 离开中枢之后的回调没有回到中枢
+            PivotEvent::Finish(pivot, Some(leave)) => {
+                self.leave = Some((pivot.clone(), *leave));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn on_divergence(&mut self, divergence: &Divergence, time: Time) -> Option<Signal> {
+        debug_assert!(divergence.level == self.level);
+        let fractal = self.find_fractal(divergence.leave.to().time())?.clone();
+        let side = if divergence.is_up() {
+            Side::Sell
+        } else {
+            Side::Buy
+        };
+        let point = FirstPoint {
+            level: self.level,
+            side,
+            fractal,
+            divergence: divergence.clone(),
+            time,
+        };
+        self.first = Some((point.clone(), Vec::new()));
+        Some(Signal::First(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macd::MacdArea;
+    use crate::pivot::PivotDetector;
+    use crate::test_util::tests::*;
+
+    // 前7个点：1 -> 4 -> 2 -> 3 -> 2.5 -> 5 -> x，在5处形成第一类卖点，之后的点依次输入
+    fn feed(prices: &[f64]) -> Vec<Signal> {
+        let fractals = make_fractals(prices);
+        let mut sd = SignalDetector::new(PivotLevel::Pen);
+        let mut signals = Vec::new();
+        sd.on_pen_event(
+            &PenEvent::First(fractals[0].clone(), fractals[1].clone()),
+            0,
+        );
+        for f in &fractals[2..7] {
+            signals.extend(sd.on_pen_event(&PenEvent::New(f.clone()), f.time()));
+        }
+
+        let line = |i: usize| Line::new_from_pen(&fractals[i], &fractals[i + 1]);
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        for i in 0..4 {
+            for event in pd.on_new_line(line(i)) {
+                sd.on_pivot_event(&event);
+            }
+        }
+        let divergence = Divergence {
+            level: PivotLevel::Pen,
+            pivot: pd.pivot().unwrap().clone(),
+            enter: line(0),
+            enter_area: MacdArea::default(),
+            leave: line(4),
+            leave_area: MacdArea::default(),
+        };
+        signals.extend(sd.on_divergence(&divergence, fractals[6].time()));
+
+        for f in &fractals[7..] {
+            signals.extend(sd.on_pen_event(&PenEvent::New(f.clone()), f.time()));
+        }
+        signals
+    }
+
+    #[test]
+    fn test_first_point() {
+        let signals = feed(&[1.0, 4.0, 2.0, 3.0, 2.5, 5.0, 4.0]);
+        assert_eq!(signals.len(), 1);
+        match &signals[0] {
+            Signal::First(point) => {
+                assert_eq!(point.side, Side::Sell);
+                assert_eq!(point.price(), 5.0);
+                assert_eq!(point.fractal.time(), 16);
+                assert_eq!(point.time, 19);
+                assert_eq!(point.pivot().zg, 3.0);
+            }
+            _ => panic!("expect Signal::First"),
+        }
+    }
+
+    #[test]
+    fn test_second_point() {
+        // 回调不创新高，在回调的一笔确认时发出第二类卖点
+        let signals = feed(&[1.0, 4.0, 2.0, 3.0, 2.5, 5.0, 4.0, 4.8, 4.2]);
+        assert_eq!(signals.len(), 2);
+        match &signals[1] {
+            Signal::Second(point) => {
+                assert_eq!(point.side, Side::Sell);
+                assert_eq!(point.price(), 4.8);
+                assert_eq!(point.invalidation, 5.0);
+                assert_eq!(point.pullback.low(), 4.0);
+                assert_eq!(point.first.price(), 5.0);
+                assert_eq!(point.time, 25);
+                assert!(!point.coincide);
+            }
+            _ => panic!("expect Signal::Second"),
+        }
+
+        // 回调创新高，第一类卖点失效
+        let signals = feed(&[1.0, 4.0, 2.0, 3.0, 2.5, 5.0, 4.0, 5.2, 4.2, 4.8, 4.5]);
+        assert_eq!(signals.len(), 1);
+
+        // 反弹跌破中枢，回调不回到中枢，二三卖点重合
+        let signals = feed(&[1.0, 4.0, 2.0, 3.0, 2.5, 5.0, 2.0, 2.4, 1.5]);
+        match &signals[1] {
+            Signal::Second(point) => assert!(point.coincide),
+            _ => panic!("expect Signal::Second"),
+        }
+    }
+
+    // 价格路径：1 -> 4 -> 2 -> 3.5 -> 2.5 -> 5 -> 4 -> 6
+    // 中枢[2.5, 3.5]，2.5 -> 5离开中枢，回调到4不回到中枢
+    fn feed_third() -> (SignalDetector, Vec<Signal>, Vec<Fractal>) {
+        let fractals = make_fractals(&[1.0, 4.0, 2.0, 3.5, 2.5, 5.0, 4.0, 6.0]);
+        let mut sd = SignalDetector::new(PivotLevel::Pen);
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        let mut signals = Vec::new();
+        sd.on_pen_event(
+            &PenEvent::First(fractals[0].clone(), fractals[1].clone()),
+            0,
+        );
+        for i in 2..fractals.len() {
+            let line = Line::new_from_pen(&fractals[i - 2], &fractals[i - 1]);
+            for event in pd.on_new_line(line) {
+                sd.on_pivot_event(&event);
+            }
+            let f = &fractals[i];
+            signals.extend(sd.on_pen_event(&PenEvent::New(f.clone()), f.time()));
+        }
+        (sd, signals, fractals)
+    }
+
+    #[test]
+    fn test_third_point() {
+        let (mut sd, signals, fractals) = feed_third();
+        assert_eq!(signals.len(), 1);
+        match &signals[0] {
+            Signal::Third(point) => {
+                assert_eq!(point.side, Side::Buy);
+                assert_eq!(point.price(), 4.0);
+                assert_eq!((point.zd(), point.zg()), (2.5, 3.5));
+                assert_eq!(point.breakout.high(), 5.0);
+                assert_eq!(point.time, fractals[7].time());
+            }
+            _ => panic!("expect Signal::Third"),
+        }
+
+        // 信号K线回到中枢，取消第三类买点
+        match sd.on_new_bar(&Bar::new(30, 4.5, 4.5, 3.4, 3.5)) {
+            Some(Signal::ThirdCancel(point, time)) => {
+                assert_eq!(point.price(), 4.0);
+                assert_eq!(time, 30);
+            }
+            _ => panic!("expect Signal::ThirdCancel"),
+        }
+        assert!(sd.on_new_bar(&Bar::new(31, 3.5, 3.5, 3.0, 3.2)).is_none());
+
+        // 信号K线结束之后再回到中枢，不再取消
+        let (mut sd, _, _) = feed_third();
+        assert!(sd.on_new_bar(&Bar::new(30, 4.5, 4.5, 3.6, 3.8)).is_none());
+        assert!(sd.on_new_bar(&Bar::new(31, 3.8, 3.9, 3.4, 3.5)).is_none());
+    }
+}
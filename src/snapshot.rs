@@ -0,0 +1,97 @@
+use std::fmt;
+
+use crate::analyzer::Analyzer;
+
+// Analyzer状态的快照，重启服务时从快照恢复，不需要重新处理所有历史K线
+// 格式：4字节小端版本号 + bincode编码的Analyzer
+// Analyzer或任何检测器的状态发生不兼容的变化时需要增加版本号
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    // 快照的版本与当前版本不一致
+    Version(u32),
+    Decode(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Version(version) => write!(
+                f,
+                "snapshot version {} is not supported, expect {}",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Decode(err) => write!(f, "invalid snapshot: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Analyzer {
+    // 未完成K线的临时结果和订阅者不包含在快照中
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut bytes = SNAPSHOT_VERSION.to_le_bytes().to_vec();
+        bincode::serialize_into(&mut bytes, self).unwrap();
+        bytes
+    }
+
+    pub fn restore(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        if bytes.len() < 4 {
+            return Err(SnapshotError::Decode("missing version".to_string()));
+        }
+        let (header, body) = bytes.split_at(4);
+        let version = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(version));
+        }
+        bincode::deserialize(body).map_err(|e| SnapshotError::Decode(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::*;
+
+    #[test]
+    fn test_snapshot_resume() {
+        let bars = load_eurusd_2021();
+        let mut expected = Analyzer::new();
+        for bar in &bars {
+            expected.on_new_bar(bar);
+        }
+
+        let split = bars.len() / 2;
+        let mut analyzer = Analyzer::new();
+        for bar in &bars[..split] {
+            analyzer.on_new_bar(bar);
+        }
+        let snapshot = analyzer.snapshot();
+        drop(analyzer);
+        let mut analyzer = Analyzer::restore(&snapshot).unwrap();
+        for bar in &bars[split..] {
+            analyzer.on_new_bar(bar);
+        }
+
+        // 恢复后继续运行与不中断运行的完整状态相同
+        assert_eq!(analyzer.snapshot(), expected.snapshot());
+        assert_eq!(analyzer.pens().len(), expected.pens().len());
+        assert_eq!(analyzer.signals().len(), expected.signals().len());
+    }
+
+    #[test]
+    fn test_snapshot_version() {
+        let mut snapshot = Analyzer::new().snapshot();
+        snapshot[0] = 0;
+        assert!(matches!(
+            Analyzer::restore(&snapshot),
+            Err(SnapshotError::Version(0))
+        ));
+        assert!(matches!(
+            Analyzer::restore(&snapshot[..2]),
+            Err(SnapshotError::Decode(_))
+        ));
+    }
+}
@@ -1,6 +1,8 @@
 #[cfg(test)]
 pub mod tests {
     use crate::bar::Bar;
+    use crate::candle::Candle;
+    use crate::fractal::Fractal;
     use chrono::prelude::*;
     use csv;
 
@@ -34,4 +36,76 @@ pub mod tests {
         }
         bars
     }
+
+    // 构造笔的端点，index同时作为时间
+    #[allow(dead_code)]
+    pub fn make_fractal(index: u64, price: f64, is_top: bool) -> Fractal {
+        let time = index as i64 * 3;
+        let (k1, k2, k3) = if is_top {
+            (
+                Candle::new(
+                    index * 3,
+                    time,
+                    price - 1.0,
+                    price - 1.0,
+                    price - 2.0,
+                    price - 2.0,
+                ),
+                Candle::new(
+                    index * 3 + 1,
+                    time + 1,
+                    price,
+                    price,
+                    price - 0.5,
+                    price - 0.5,
+                ),
+                Candle::new(
+                    index * 3 + 2,
+                    time + 2,
+                    price - 1.0,
+                    price - 1.0,
+                    price - 2.0,
+                    price - 2.0,
+                ),
+            )
+        } else {
+            (
+                Candle::new(
+                    index * 3,
+                    time,
+                    price + 2.0,
+                    price + 2.0,
+                    price + 1.0,
+                    price + 1.0,
+                ),
+                Candle::new(
+                    index * 3 + 1,
+                    time + 1,
+                    price + 0.5,
+                    price + 0.5,
+                    price,
+                    price,
+                ),
+                Candle::new(
+                    index * 3 + 2,
+                    time + 2,
+                    price + 2.0,
+                    price + 2.0,
+                    price + 1.0,
+                    price + 1.0,
+                ),
+            )
+        };
+        Fractal::new(k1, k2, k3)
+    }
+
+    // prices[0]为底，之后顶底交替
+    #[allow(dead_code)]
+    pub fn make_fractals(prices: &[f64]) -> Vec<Fractal> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, p)| make_fractal(i as u64, *p, i % 2 == 1))
+            .collect()
+    }
 }
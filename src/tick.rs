@@ -0,0 +1,185 @@
+use crate::analyzer::Analyzer;
+use crate::bar::Bar;
+use crate::time::Time;
+
+// 逐笔成交或报价，报价取买卖中间价
+#[derive(Debug, Clone, Copy)]
+pub struct Tick {
+    pub time: Time,
+    pub price: f64,
+    pub volume: f64,
+}
+
+impl Tick {
+    pub fn new(time: Time, price: f64, volume: f64) -> Self {
+        Self {
+            time,
+            price,
+            volume,
+        }
+    }
+
+    pub fn mid(time: Time, bid: f64, ask: f64, volume: f64) -> Self {
+        Self::new(time, (bid + ask) / 2.0, volume)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    // 按时间合成，毫秒，K线时间为周期的开始时间
+    Time(Time),
+    // 按tick数量合成，K线时间为第一个tick的时间
+    Ticks(usize),
+}
+
+// 由tick合成K线，K线的成交量为tick成交量之和
+// 每根K线完成时调用Analyzer::on_bar_close，未完成时调用Analyzer::on_bar_update
+#[derive(Debug)]
+pub struct TickAggregator {
+    interval: Interval,
+    analyzer: Analyzer,
+    // 正在形成的K线
+    bar: Option<Bar>,
+    count: usize,
+}
+
+impl TickAggregator {
+    // analyzer可以是指定了笔划分规则或者从快照恢复的Analyzer
+    pub fn new(interval: Interval, analyzer: Analyzer) -> Self {
+        debug_assert!(match interval {
+            Interval::Time(t) => t > 0,
+            Interval::Ticks(n) => n > 0,
+        });
+        Self {
+            interval,
+            analyzer,
+            bar: None,
+            count: 0,
+        }
+    }
+
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    // 用于订阅事件
+    pub fn analyzer_mut(&mut self) -> &mut Analyzer {
+        &mut self.analyzer
+    }
+
+    pub fn into_analyzer(self) -> Analyzer {
+        self.analyzer
+    }
+
+    // 当前尚未完成的K线
+    pub fn partial(&self) -> Option<&Bar> {
+        self.bar.as_ref()
+    }
+
+    fn close(&mut self) -> Option<Bar> {
+        let bar = self.bar.take()?;
+        self.count = 0;
+        self.analyzer.on_bar_close(&bar);
+        Some(bar)
+    }
+
+    // 时间周期的K线需要下一个tick才知道已经结束，行情停顿时可以用定时器结束K线
+    pub fn on_timer(&mut self, time: Time) -> Option<Bar> {
+        match (self.interval, self.bar.as_ref()) {
+            (Interval::Time(period), Some(bar)) if time >= bar.time + period => self.close(),
+            _ => None,
+        }
+    }
+
+    // 返回已经完成的K线
+    pub fn on_tick(&mut self, tick: &Tick) -> Option<Bar> {
+        let completed = self.on_timer(tick.time);
+
+        let start = match self.interval {
+            Interval::Time(period) => tick.time - tick.time.rem_euclid(period),
+            Interval::Ticks(_) => tick.time,
+        };
+        match self.bar.as_mut() {
+            Some(bar) => {
+                bar.high = bar.high.max(tick.price);
+                bar.low = bar.low.min(tick.price);
+                bar.close = tick.price;
+                bar.volume += tick.volume;
+            }
+            None => {
+                let price = tick.price;
+                let bar = Bar::new(start, price, price, price, price).with_volume(tick.volume);
+                self.bar = Some(bar);
+            }
+        }
+        self.count += 1;
+
+        match self.interval {
+            Interval::Ticks(n) if self.count >= n => {
+                // 同一个tick不可能既结束上一根时间K线又结束tick数量K线
+                debug_assert!(completed.is_none());
+                self.close()
+            }
+            _ => {
+                let bar = self.bar.as_ref().unwrap();
+                self.analyzer.on_bar_update(bar);
+                completed
+            }
+        }
+    }
+
+    pub fn on_quote(&mut self, time: Time, bid: f64, ask: f64) -> Option<Bar> {
+        self.on_tick(&Tick::mid(time, bid, ask, 0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::{MINUTE_UNIT, SECOND_UNIT};
+
+    #[test]
+    fn test_time_interval() {
+        let mut ta = TickAggregator::new(Interval::Time(MINUTE_UNIT), Analyzer::new());
+        let prices = [1.0, 3.0, 0.5, 2.0];
+        let mut bars = Vec::new();
+        assert!(ta.on_tick(&Tick::new(5, prices[0], 1.0)).is_none());
+        assert!(ta.analyzer().macd().last().is_none());
+        for (i, price) in prices.iter().enumerate().skip(1) {
+            let tick = Tick::new(i as i64 * 20 * SECOND_UNIT + 5, *price, 1.0);
+            bars.extend(ta.on_tick(&tick));
+        }
+        // 第4个tick在1分钟之后，第一根K线结束
+        assert_eq!(bars.len(), 1);
+        let bar = &bars[0];
+        assert_eq!(bar.time, 0);
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (1.0, 3.0, 0.5, 0.5)
+        );
+        assert_eq!(bar.volume, 3.0);
+        assert_eq!(ta.partial().unwrap().time, MINUTE_UNIT);
+        // K线完成后已经送入Analyzer
+        assert!(ta.analyzer().macd().last().is_some());
+
+        // 定时器结束K线
+        assert!(ta.on_timer(MINUTE_UNIT + 30 * SECOND_UNIT).is_none());
+        assert_eq!(ta.on_timer(2 * MINUTE_UNIT).unwrap().close, 2.0);
+        assert!(ta.partial().is_none());
+    }
+
+    #[test]
+    fn test_tick_interval() {
+        let mut ta = TickAggregator::new(Interval::Ticks(3), Analyzer::new());
+        let mut bars = Vec::new();
+        for i in 0..7 {
+            bars.extend(ta.on_quote(i * SECOND_UNIT, i as f64, i as f64 + 0.5));
+        }
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].time, 0);
+        assert_eq!((bars[0].open, bars[0].close), (0.25, 2.25));
+        assert_eq!(bars[0].volume, 0.0);
+        assert_eq!(bars[1].time, 3 * SECOND_UNIT);
+        assert_eq!(ta.partial().unwrap().open, 6.25);
+    }
+}
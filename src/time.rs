@@ -3,3 +3,5 @@ pub type Time = i64;
 
 pub const SECOND_UNIT: i64 = 1000; // 每秒 = 1000毫秒
 pub const MINUTE_UNIT: i64 = SECOND_UNIT * 60;
+pub const HOUR_UNIT: i64 = MINUTE_UNIT * 60;
+pub const DAY_UNIT: i64 = HOUR_UNIT * 24;
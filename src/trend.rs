@@ -0,0 +1,240 @@
+use crate::pivot::{Pivot, PivotEvent, PivotLevel};
+use crate::time::Time;
+
+// 走势类型
+// 走势由同级别中枢构成
+// 1. 盘整：只有一个中枢
+// 2. 趋势：两个及以上同向且依次不重叠的中枢，依次向上为上涨，依次向下为下跌
+// 走势类型的判断依赖中枢之间的关系
+// 1. 新生：新中枢与前中枢不重叠，同向则趋势延续，盘整升级为趋势，反向则当前走势结束
+// 2. 扩张：盘整的中枢扩张，仍然是盘整；趋势的最后一个中枢扩张，趋势结束，以扩张后的中枢开始新的盘整
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrendType {
+    // 盘整
+    Consolidation,
+    // 上涨
+    Up,
+    // 下跌
+    Down,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trend {
+    pub level: PivotLevel,
+    pub trend_type: TrendType,
+    pub pivots: Vec<Pivot>,
+}
+
+impl Trend {
+    fn new(level: PivotLevel, pivot: Pivot) -> Self {
+        Self {
+            level,
+            trend_type: TrendType::Consolidation,
+            pivots: vec![pivot],
+        }
+    }
+
+    pub fn pivot_count(&self) -> usize {
+        self.pivots.len()
+    }
+
+    pub fn start(&self) -> Time {
+        self.pivots[0].start
+    }
+
+    pub fn end(&self) -> Time {
+        self.pivots[self.pivots.len() - 1].end
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrendEvent {
+    // 新的走势开始，总是从盘整开始
+    New(Trend),
+    // 走势类型或者中枢数量发生变化
+    Update(Trend),
+    // 走势结束
+    Finish(Trend),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrendDetector {
+    level: PivotLevel,
+    current: Option<Trend>,
+    // 最后一个中枢是扩张形成的高级别中枢时，(扩张前的部分, 最后参与扩张的中枢)
+    expansion: Option<(Pivot, Pivot)>,
+}
+
+impl TrendDetector {
+    pub fn new(level: PivotLevel) -> Self {
+        Self {
+            level,
+            current: None,
+            expansion: None,
+        }
+    }
+
+    // 当前走势
+    pub fn current(&self) -> Option<&Trend> {
+        self.current.as_ref()
+    }
+
+    // 中枢延伸或者结束时，更新最后一个中枢
+    // 扩张形成的高级别中枢随最后参与扩张的中枢一起延伸
+    fn update_last(&mut self, pivot: &Pivot) {
+        let last = match self.current.as_mut() {
+            Some(current) => current.pivots.last_mut().unwrap(),
+            None => return,
+        };
+        if last.start == pivot.start {
+            *last = pivot.clone();
+            return;
+        }
+        if let Some((base, component)) = self.expansion.as_mut() {
+            if component.start == pivot.start {
+                *component = pivot.clone();
+                let mut expanded = base.merge(pivot);
+                expanded.level = last.level;
+                *last = expanded;
+            }
+        }
+    }
+
+    fn start_new(&mut self, pivot: Pivot) -> TrendEvent {
+        let trend = Trend::new(self.level, pivot);
+        self.current = Some(trend.clone());
+        TrendEvent::New(trend)
+    }
+
+    fn on_reborn(&mut self, last: &Pivot, pivot: &Pivot) -> Vec<TrendEvent> {
+        let trend_type = if pivot.dd >= last.gg {
+            TrendType::Up
+        } else {
+            TrendType::Down
+        };
+
+        let current = self.current.as_mut().unwrap();
+        if current.trend_type == TrendType::Consolidation || current.trend_type == trend_type {
+            // 盘整升级为趋势，或者趋势延续
+            current.trend_type = trend_type;
+            current.pivots.push(pivot.clone());
+            self.expansion = None;
+            vec![TrendEvent::Update(current.clone())]
+        } else {
+            // 反向新生，当前趋势结束
+            let finished = self.current.take().unwrap();
+            self.expansion = None;
+            vec![TrendEvent::Finish(finished), self.start_new(pivot.clone())]
+        }
+    }
+
+    fn on_expand(&mut self, pivot: &Pivot, expanded: &Pivot) -> Vec<TrendEvent> {
+        let current = self.current.as_mut().unwrap();
+        if current.trend_type == TrendType::Consolidation {
+            // 盘整中枢扩张，仍然是盘整
+            let base = current.pivots.pop().unwrap();
+            current.pivots.push(expanded.clone());
+            self.expansion = Some((base, pivot.clone()));
+            vec![TrendEvent::Update(current.clone())]
+        } else {
+            // 趋势的最后一个中枢扩张，趋势结束
+            let finished = self.current.take().unwrap();
+            let base = finished.pivots.last().unwrap().clone();
+            self.expansion = Some((base, pivot.clone()));
+            vec![
+                TrendEvent::Finish(finished),
+                self.start_new(expanded.clone()),
+            ]
+        }
+    }
+
+    pub fn on_pivot_event(&mut self, pivot_event: &PivotEvent) -> Vec<TrendEvent> {
+        match pivot_event {
+            PivotEvent::New(pivot) => {
+                // 后续的中枢会同时发出扩张或新生事件，这里只处理第一个中枢
+                if self.current.is_none() {
+                    return vec![self.start_new(pivot.clone())];
+                }
+                Vec::new()
+            }
+
+            PivotEvent::Extend(pivot) | PivotEvent::Finish(pivot, _) => {
+                self.update_last(pivot);
+                Vec::new()
+            }
+
+            PivotEvent::Leave(_, _) => Vec::new(),
+
+            PivotEvent::Expand(_, pivot, expanded) => self.on_expand(pivot, expanded),
+
+            PivotEvent::Reborn(last, pivot) => self.on_reborn(last, pivot),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line::Line;
+    use crate::pivot::PivotDetector;
+
+    fn feed(td: &mut TrendDetector, prices: &[f64]) -> Vec<TrendEvent> {
+        let mut pd = PivotDetector::new(PivotLevel::Pen);
+        let mut events = Vec::new();
+        for i in 1..prices.len() {
+            let line = Line::new(i as i64 - 1, prices[i - 1], i as i64, prices[i]);
+            for pivot_event in pd.on_new_line(line) {
+                events.extend(td.on_pivot_event(&pivot_event));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_up_trend() {
+        let mut td = TrendDetector::new(PivotLevel::Pen);
+        // 中枢A [3, 4]，中枢B [5.8, 6]，B在A之上，上涨
+        let events = feed(&mut td, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0, 5.5, 7.0, 5.8]);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], TrendEvent::New(_)));
+        let trend = td.current().unwrap();
+        assert_eq!(trend.trend_type, TrendType::Up);
+        assert_eq!(trend.pivot_count(), 2);
+        assert_eq!(trend.start(), 1);
+    }
+
+    #[test]
+    fn test_consolidation() {
+        let mut td = TrendDetector::new(PivotLevel::Pen);
+        // 中枢B与中枢A波动区间重叠，扩张，仍然是盘整
+        let events = feed(&mut td, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0, 4.5, 5.5, 4.8]);
+        assert_eq!(events.len(), 2);
+        let trend = td.current().unwrap();
+        assert_eq!(trend.trend_type, TrendType::Consolidation);
+        assert_eq!(trend.pivot_count(), 1);
+        assert_eq!(trend.pivots[0].level, PivotLevel::Segment);
+    }
+
+    #[test]
+    fn test_expanded_extend() {
+        let mut td = TrendDetector::new(PivotLevel::Pen);
+        let prices = [1.0, 4.0, 2.0, 5.0, 3.0, 6.0, 4.5, 5.5, 4.8];
+        feed(&mut td, &prices);
+        let expanded = td.current().unwrap().pivots[0].clone();
+
+        // 中枢B继续延伸，扩张后的中枢随之延伸
+        let mut td = TrendDetector::new(PivotLevel::Pen);
+        feed(&mut td, &[&prices[..], &[5.2, 5.0]].concat());
+        let trend = td.current().unwrap();
+        assert_eq!(trend.pivot_count(), 1);
+        assert_eq!(trend.pivots[0].level, PivotLevel::Segment);
+        assert_eq!(trend.pivots[0].start, expanded.start);
+        assert_eq!(trend.end(), 9);
+        assert_eq!(trend.pivots[0].count, expanded.count + 1);
+    }
+}
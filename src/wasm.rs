@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::analyzer::Analyzer;
+use crate::bar::Bar;
+use crate::fractal::{Fractal, FractalType};
+use crate::lifecycle::Tracked;
+use crate::line::{Line, Point};
+
+// WebAssembly绑定，用于node/deno
+// 编译：cargo build --lib --release --target wasm32-unknown-unknown --features wasm
+// 然后用wasm-bindgen --target nodejs(或deno)生成JS胶水代码
+// JS中时间为毫秒数(number)，返回值都是普通的JS对象
+
+#[derive(Deserialize)]
+struct BarInput {
+    time: f64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+}
+
+#[derive(Serialize)]
+struct PointOutput {
+    time: f64,
+    price: f64,
+}
+
+impl From<Point> for PointOutput {
+    fn from(point: Point) -> Self {
+        Self {
+            time: point.time() as f64,
+            price: point.price(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FractalOutput {
+    id: u64,
+    time: f64,
+    price: f64,
+    // "top"或"bottom"
+    #[serde(rename = "type")]
+    fractal_type: &'static str,
+    confirmed: bool,
+}
+
+impl From<&Tracked<Fractal>> for FractalOutput {
+    fn from(f: &Tracked<Fractal>) -> Self {
+        Self {
+            id: f.id,
+            time: f.value.time() as f64,
+            price: f.value.price(),
+            fractal_type: match f.value.fractal_type() {
+                FractalType::Top => "top",
+                FractalType::Bottom => "bottom",
+            },
+            confirmed: f.confirmed,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct LineOutput {
+    id: u64,
+    from: PointOutput,
+    to: PointOutput,
+    confirmed: bool,
+}
+
+impl From<&Tracked<Line>> for LineOutput {
+    fn from(line: &Tracked<Line>) -> Self {
+        Self {
+            id: line.id,
+            from: line.value.from().into(),
+            to: line.value.to().into(),
+            confirmed: line.confirmed,
+        }
+    }
+}
+
+fn to_js<T: Serialize>(value: &T) -> JsValue {
+    serde_wasm_bindgen::to_value(value).unwrap()
+}
+
+#[wasm_bindgen(js_name = Analyzer)]
+pub struct WasmAnalyzer {
+    analyzer: Analyzer,
+}
+
+#[wasm_bindgen(js_class = Analyzer)]
+impl WasmAnalyzer {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            analyzer: Analyzer::new(),
+        }
+    }
+
+    #[wasm_bindgen(js_name = pushBar)]
+    pub fn push_bar(&mut self, time: f64, open: f64, high: f64, low: f64, close: f64) {
+        let bar = Bar::new(time as i64, open, high, low, close);
+        self.analyzer.on_new_bar(&bar);
+    }
+
+    // 一批K线，每列一个Float64Array，长度必须相同
+    #[wasm_bindgen(js_name = pushArrays)]
+    pub fn push_arrays(
+        &mut self,
+        time: &[f64],
+        open: &[f64],
+        high: &[f64],
+        low: &[f64],
+        close: &[f64],
+    ) -> Result<(), JsError> {
+        let len = time.len();
+        if [open.len(), high.len(), low.len(), close.len()]
+            .iter()
+            .any(|l| *l != len)
+        {
+            return Err(JsError::new("arrays must have the same length"));
+        }
+        for i in 0..len {
+            self.push_bar(time[i], open[i], high[i], low[i], close[i]);
+        }
+        Ok(())
+    }
+
+    // {time, open, high, low, close}对象或者这种对象的数组
+    #[wasm_bindgen(js_name = pushObjects)]
+    pub fn push_objects(&mut self, bars: JsValue) -> Result<(), JsError> {
+        let bars: Vec<BarInput> = if bars.is_array() {
+            serde_wasm_bindgen::from_value(bars)?
+        } else {
+            vec![serde_wasm_bindgen::from_value(bars)?]
+        };
+        for bar in bars {
+            self.push_bar(bar.time, bar.open, bar.high, bar.low, bar.close);
+        }
+        Ok(())
+    }
+
+    pub fn fractals(&self) -> JsValue {
+        let fractals: Vec<FractalOutput> =
+            self.analyzer.fractals().iter().map(Into::into).collect();
+        to_js(&fractals)
+    }
+
+    // 笔的端点
+    #[wasm_bindgen(js_name = penPoints)]
+    pub fn pen_points(&self) -> JsValue {
+        let points: Vec<FractalOutput> =
+            self.analyzer.pen_points().iter().map(Into::into).collect();
+        to_js(&points)
+    }
+
+    pub fn pens(&self) -> JsValue {
+        let pens: Vec<LineOutput> = self.analyzer.pens().iter().map(Into::into).collect();
+        to_js(&pens)
+    }
+
+    pub fn segments(&self) -> JsValue {
+        let segments: Vec<LineOutput> = self.analyzer.segments().iter().map(Into::into).collect();
+        to_js(&segments)
+    }
+}
+
+impl Default for WasmAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
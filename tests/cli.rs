@@ -0,0 +1,83 @@
+// 用rzen analyze分析EURUSD样本数据，检查各种输出格式的内容一致
+#![cfg(feature = "cli")]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn analyze(args: &[&str]) -> String {
+    let csv =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("data/EURUSD-2021_01_01-2021_04_08.csv");
+    let output = Command::new(env!("CARGO_BIN_EXE_rzen"))
+        .arg("analyze")
+        .arg(csv)
+        .args(["--timezone", "Asia/Shanghai"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_formats() {
+    let json: serde_json::Value = serde_json::from_str(&analyze(&["--format", "json"])).unwrap();
+    let pens = json["pens"].as_array().unwrap();
+    assert!(!pens.is_empty());
+    assert!(!json["candles"].as_array().unwrap().is_empty());
+    assert!(!json["pivots"].as_array().unwrap().is_empty());
+    // 相邻的笔首尾相接
+    for pair in pens.windows(2) {
+        assert_eq!(pair[0]["to_time"], pair[1]["from_time"]);
+    }
+
+    let jsonl = analyze(&["--format", "jsonl", "--objects", "pens"]);
+    assert_eq!(jsonl.lines().count(), pens.len());
+    let first: serde_json::Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+    assert_eq!(first["type"], "pen");
+    assert_eq!(first["from_time"], pens[0]["from_time"]);
+
+    let csv = analyze(&["--format", "csv", "--objects", "segments"]);
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().starts_with("type,id,time,"));
+    assert_eq!(lines.count(), json["segments"].as_array().unwrap().len());
+}
+
+#[test]
+fn test_fractals() {
+    let json: serde_json::Value = serde_json::from_str(&analyze(&[
+        "--format",
+        "json",
+        "--objects",
+        "fractals,pen-points,pens",
+    ]))
+    .unwrap();
+    let fractals = json["fractals"].as_array().unwrap();
+    let points = json["pen_points"].as_array().unwrap();
+    assert_eq!(points.len(), json["pens"].as_array().unwrap().len() + 1);
+    assert!(fractals.len() > points.len());
+    assert_eq!(fractals[0]["type"], "fractal");
+    assert_eq!(points[0]["type"], "pen_point");
+    // 笔的端点都是分型检测得到的分型
+    assert!(points
+        .iter()
+        .all(|p| fractals.iter().any(|f| f["time"] == p["time"])));
+}
+
+#[test]
+fn test_pen_rule() {
+    let standard = analyze(&["--format", "jsonl", "--objects", "pens"]);
+    let relaxed = analyze(&[
+        "--format",
+        "jsonl",
+        "--objects",
+        "pens",
+        "--pen-rule",
+        "relaxed",
+    ]);
+    // 新笔的条件更宽松，划分出更多的笔
+    assert!(relaxed.lines().count() > standard.lines().count());
+}
@@ -0,0 +1,48 @@
+// 用系统的C编译器编译tests/ffi_test.c，链接librzen动态库并运行
+#![cfg(unix)]
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn test_c_program() {
+    // 测试程序位于target/<profile>/deps，动态库位于target/<profile>
+    let exe = env::current_exe().unwrap();
+    let target = exe.parent().unwrap().parent().unwrap().to_path_buf();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let program = target.join("ffi_test");
+
+    // cargo test只编译rlib，需要单独编译动态库
+    let mut build = Command::new(env!("CARGO"));
+    build.args(["build", "--lib"]).current_dir(&root);
+    if target.ends_with("release") {
+        build.arg("--release");
+    }
+    assert!(build.status().unwrap().success());
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .arg(root.join("tests/ffi_test.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&target)
+        .args(["-lrzen", "-lm", "-o"])
+        .arg(&program)
+        .status()
+        .expect("C compiler not found");
+    assert!(status.success());
+
+    let output = Command::new(&program)
+        .env("LD_LIBRARY_PATH", &target)
+        .env("DYLD_LIBRARY_PATH", &target)
+        .output()
+        .unwrap();
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,60 @@
+/* 通过C接口输入K线，检查事件的ID引用是否一致 */
+#include <math.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "rzen.h"
+
+#define MAX_ID (1 << 20)
+#define BARS 20000
+
+static unsigned char seen[MAX_ID];
+
+int main(void) {
+    RzenAnalyzer *analyzer = rzen_analyzer_new();
+    RzenEvent events[16];
+    size_t counts[7][4];
+    memset(counts, 0, sizeof(counts));
+
+    for (int i = 0; i < BARS; i++) {
+        double price = 100.0 + 10.0 * sin(i / 50.0) + 3.0 * sin(i / 7.0);
+        int64_t time = (int64_t)i * 60 * 1000;
+        rzen_analyzer_push_bar(analyzer, time, price, price + 0.5, price - 0.5, price);
+
+        size_t n;
+        while ((n = rzen_analyzer_poll_events(analyzer, events, 16)) > 0) {
+            for (size_t k = 0; k < n; k++) {
+                RzenEvent *e = &events[k];
+                counts[e->kind][e->action]++;
+                if (e->kind == RZEN_KIND_SIGNAL) {
+                    continue;
+                }
+                if (e->id >= MAX_ID) {
+                    fprintf(stderr, "id %llu out of range\n", (unsigned long long)e->id);
+                    return 1;
+                }
+                if (e->action == RZEN_ACTION_NEW) {
+                    seen[e->id] = 1;
+                } else if (!seen[e->id]) {
+                    fprintf(stderr, "unknown id %llu\n", (unsigned long long)e->id);
+                    return 1;
+                }
+            }
+        }
+    }
+
+    if (rzen_analyzer_pending_events(analyzer) != 0) {
+        return 1;
+    }
+    rzen_analyzer_free(analyzer);
+
+    printf("pens = %zu, segments = %zu, pivots = %zu, signals = %zu\n",
+           counts[RZEN_KIND_PEN][RZEN_ACTION_NEW],
+           counts[RZEN_KIND_SEGMENT][RZEN_ACTION_CONFIRM],
+           counts[RZEN_KIND_PIVOT][RZEN_ACTION_NEW],
+           counts[RZEN_KIND_SIGNAL][RZEN_ACTION_NEW]);
+    if (counts[RZEN_KIND_PEN][RZEN_ACTION_NEW] == 0 || counts[RZEN_KIND_SEGMENT][RZEN_ACTION_CONFIRM] == 0) {
+        return 1;
+    }
+    return 0;
+}
@@ -0,0 +1,43 @@
+// 编译python扩展模块，用python运行tests/python_test.py，需要安装numpy
+#![cfg(all(target_os = "linux", feature = "python"))]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn test_python() {
+    // 测试程序位于target/<profile>/deps，动态库位于target/<profile>
+    let exe = env::current_exe().unwrap();
+    let target = exe.parent().unwrap().parent().unwrap().to_path_buf();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+    let mut build = Command::new(env!("CARGO"));
+    build
+        .args(["build", "--lib", "--features", "python"])
+        .current_dir(&root);
+    if target.ends_with("release") {
+        build.arg("--release");
+    }
+    assert!(build.status().unwrap().success());
+
+    // python按模块名加载rzen.so
+    let module = target.join("python-test");
+    fs::create_dir_all(&module).unwrap();
+    fs::copy(target.join("librzen.so"), module.join("rzen.so")).unwrap();
+
+    let python = env::var("PYTHON").unwrap_or_else(|_| "python3".to_string());
+    let output = Command::new(python)
+        .arg(root.join("tests/python_test.py"))
+        .arg(&module)
+        .arg(root.join("data/EURUSD-2021_01_01-2021_04_08.csv"))
+        .output()
+        .expect("python not found");
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,56 @@
+# 用python运行编译好的扩展模块：python python_test.py <模块目录> <csv文件>
+import csv
+import sys
+from datetime import datetime, timedelta, timezone
+
+import numpy as np
+
+sys.path.insert(0, sys.argv[1])
+import rzen
+
+# 与test_util::load_datetime_bar相同，时间为GMT+8
+rows = []
+with open(sys.argv[2]) as f:
+    for row in csv.DictReader(f):
+        dt = datetime.strptime(row["datetime"], "%Y-%m-%d %H:%M:%S").replace(tzinfo=timezone.utc)
+        time = int((dt + timedelta(hours=8)).timestamp() * 1000)
+        rows.append((time, float(row["open"]), float(row["high"]), float(row["low"]), float(row["close"])))
+
+time = np.array([r[0] for r in rows], dtype=np.int64)
+columns = [np.array([r[i] for r in rows], dtype=np.float64) for i in range(1, 5)]
+result = rzen.analyze(time, *columns)
+
+# 批量计算与逐根K线计算的结果相同
+analyzer = rzen.Analyzer()
+for row in rows:
+    analyzer.on_new_bar(*row)
+for name, stream in [
+    ("fractals", analyzer.fractals()),
+    ("pen_points", analyzer.pen_points()),
+    ("pens", analyzer.pens()),
+    ("segments", analyzer.segments()),
+    ("pivots", analyzer.pivots()),
+]:
+    assert result[name].keys() == stream.keys(), name
+    for key in stream:
+        assert np.array_equal(result[name][key], stream[key]), (name, key)
+
+pens = result["pens"]
+assert len(pens["id"]) > 0 and len(result["segments"]["id"]) > 0
+assert len(result["pen_points"]["id"]) == len(pens["id"]) + 1
+assert len(result["fractals"]["id"]) > len(result["pen_points"]["id"])
+assert np.isin(result["pen_points"]["time"], result["fractals"]["time"]).all()
+assert pens["from_time"].dtype == np.int64 and pens["confirmed"].dtype == np.bool_
+assert np.array_equal(pens["from_time"][1:], pens["to_time"][:-1])
+assert set(np.unique(result["fractals"]["type"])) <= {1, -1}
+
+try:
+    rzen.analyze(time[:2], *columns)
+    raise AssertionError("expect ValueError")
+except ValueError:
+    pass
+
+print(
+    "bars = %d, pens = %d, segments = %d, pivots = %d"
+    % (len(rows), len(pens["id"]), len(result["segments"]["id"]), len(result["pivots"]["level"]))
+)
@@ -0,0 +1,50 @@
+// 编译wasm32-unknown-unknown，用wasm-bindgen生成node模块，再用node运行tests/wasm_test.js
+// 需要安装wasm32-unknown-unknown target、与依赖版本一致的wasm-bindgen-cli以及node
+#![cfg(all(unix, feature = "wasm"))]
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn test_node() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let target = root.join("target");
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "build",
+            "--lib",
+            "--release",
+            "--target",
+            "wasm32-unknown-unknown",
+            "--features",
+            "wasm",
+        ])
+        .current_dir(&root)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let pkg = target.join("wasm-test");
+    let wasm_bindgen = env::var("WASM_BINDGEN").unwrap_or_else(|_| "wasm-bindgen".to_string());
+    let status = Command::new(wasm_bindgen)
+        .arg(target.join("wasm32-unknown-unknown/release/rzen.wasm"))
+        .args(["--target", "nodejs", "--out-dir"])
+        .arg(&pkg)
+        .status()
+        .expect("wasm-bindgen not found");
+    assert!(status.success());
+
+    let output = Command::new("node")
+        .arg(root.join("tests/wasm_test.js"))
+        .arg(&pkg)
+        .arg(root.join("data/EURUSD-2021_01_01-2021_04_08.csv"))
+        .output()
+        .expect("node not found");
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,48 @@
+// 用node运行wasm-bindgen生成的模块：node wasm_test.js <pkg目录> <csv文件>
+const assert = require('assert');
+const fs = require('fs');
+const path = require('path');
+
+const { Analyzer } = require(path.resolve(process.argv[2], 'rzen.js'));
+
+// 与test_util::load_datetime_bar相同，时间为GMT+8
+const lines = fs.readFileSync(process.argv[3], 'utf8').trim().split('\n').slice(1);
+const bars = lines.map((line) => {
+  const [datetime, open, close, high, low] = line.split(',');
+  const time = Date.parse(datetime.replace(' ', 'T') + 'Z') + 8 * 3600 * 1000;
+  return { time, open: +open, high: +high, low: +low, close: +close };
+});
+
+// 按列输入
+const columns = new Analyzer();
+const column = (key) => Float64Array.from(bars, (bar) => bar[key]);
+columns.pushArrays(column('time'), column('open'), column('high'), column('low'), column('close'));
+
+// 按对象输入，单个对象和数组
+const objects = new Analyzer();
+objects.pushObjects(bars[0]);
+objects.pushObjects(bars.slice(1));
+
+const pens = columns.pens();
+const segments = columns.segments();
+const fractals = columns.fractals();
+const points = columns.penPoints();
+assert.deepStrictEqual(objects.pens(), pens);
+assert.deepStrictEqual(objects.segments(), segments);
+
+assert.ok(pens.length > 0 && segments.length > 0);
+assert.strictEqual(points.length, pens.length + 1);
+assert.ok(fractals.length > points.length);
+assert.ok(points.every((p) => fractals.some((f) => f.time === p.time)));
+for (let i = 1; i < pens.length; i++) {
+  assert.strictEqual(pens[i].from.time, pens[i - 1].to.time);
+  assert.ok(pens[i].id > pens[i - 1].id);
+}
+assert.ok(fractals.every((f) => (f.type === 'top' || f.type === 'bottom') && f.confirmed));
+assert.ok(!pens[pens.length - 1].confirmed && pens[0].confirmed);
+
+assert.throws(() => columns.pushArrays(new Float64Array(2), new Float64Array(1), new Float64Array(2), new Float64Array(2), new Float64Array(2)));
+
+columns.free();
+objects.free();
+console.log(`bars = ${bars.length}, fractals = ${fractals.length}, pens = ${pens.length}, segments = ${segments.length}`);